@@ -1,5 +1,6 @@
 
-use std::collections::{VecDeque, HashMap};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
 
 use crate::wumpus::{
 	Coordinate,
@@ -8,7 +9,7 @@ use crate::wumpus::{
 	Class,
 	ClassField,
 	Map,
-	Game,
+	Rules,
 };
 
 use itertools::Itertools;
@@ -24,7 +25,7 @@ pub fn hide_map(map: &mut Map) {
 }
 
 
-pub fn is_map_valid(map: &Map, blacklist: &HashMap<Coordinate, Class>) -> bool {
+pub fn is_map_valid(map: &Map, blacklist: &HashMap<Coordinate, Class>, rules: &Rules) -> bool {
 
 	// All used tiles must be within the map.
 	if std::iter::empty()
@@ -49,9 +50,9 @@ pub fn is_map_valid(map: &Map, blacklist: &HashMap<Coordinate, Class>) -> bool {
 	}
 
 	// The number of classes cannot be more than possible.
-	if map.treasures.len() > Game::COUNT_TREASURES as usize { return false; }
-	if map.wumpuses.len()  > Game::COUNT_WUMPUSES  as usize { return false; }
-	if map.pits.len()      > Game::COUNT_PITS      as usize { return false; }
+	if map.treasures.len() > rules.count_treasures as usize { return false; }
+	if map.wumpuses.len()  > rules.count_wumpuses  as usize { return false; }
+	if map.pits.len()      > rules.count_pits      as usize { return false; }
 
 	// All treasures must be surrounded with glitters.
 	if ! map.treasures
@@ -83,8 +84,8 @@ pub fn is_map_valid(map: &Map, blacklist: &HashMap<Coordinate, Class>) -> bool {
 		.all(|&glitter| glitter
 			.get_neighbours()
 			.iter()
-			.filter(|&neighbour| map.encompass(&neighbour))
-			.find(|&neighbour| map.treasures.contains(&neighbour))
+			.filter(|&neighbour| map.encompass(neighbour))
+			.find(|&neighbour| map.treasures.contains(neighbour))
 			.is_some()
 		)
 	{ return false; }
@@ -95,8 +96,8 @@ pub fn is_map_valid(map: &Map, blacklist: &HashMap<Coordinate, Class>) -> bool {
 		.all(|&stench| stench
 			.get_neighbours()
 			.iter()
-			.filter(|&neighbour| map.encompass(&neighbour))
-			.find(|&neighbour| map.wumpuses.contains(&neighbour))
+			.filter(|&neighbour| map.encompass(neighbour))
+			.find(|&neighbour| map.wumpuses.contains(neighbour))
 			.is_some()
 		)
 	{ return false; }
@@ -107,8 +108,8 @@ pub fn is_map_valid(map: &Map, blacklist: &HashMap<Coordinate, Class>) -> bool {
 		.all(|&breeze| breeze
 			.get_neighbours()
 			.iter()
-			.filter(|&neighbour| map.encompass(&neighbour))
-			.find(|&neighbour| map.pits.contains(&neighbour))
+			.filter(|&neighbour| map.encompass(neighbour))
+			.find(|&neighbour| map.pits.contains(neighbour))
 			.is_some()
 		)
 	{ return false; }
@@ -117,98 +118,122 @@ pub fn is_map_valid(map: &Map, blacklist: &HashMap<Coordinate, Class>) -> bool {
 }
 
 
-pub fn pathfind(initial_location: &Coordinate, initial_direction: &Direction, map: &Map) -> (HashMap<Coordinate, Coordinate>, HashMap<Coordinate, i32>) {
+pub fn risk_cost<'a>(map: &'a Map, rules: &'a Rules, classes: &'a HashMap<Coordinate, ClassField<f64>>, target: &'a Coordinate) -> impl Fn(&Coordinate) -> Option<i32> + 'a {
+	move |location: &Coordinate| {
 
-	let mut links: HashMap<Coordinate, Coordinate> = HashMap::from([(*initial_location, *initial_location)]);
-	let mut dirs: HashMap<Coordinate, Direction> = HashMap::from([(*initial_location, *initial_direction)]);
-	let mut costs: HashMap<Coordinate, i32> = HashMap::from([(*initial_location, 0)]);
+		// Locations outside the map can never be entered.
+		if ! map.encompass(location) {
+			return None;
+		}
 
-	let mut queue: VecDeque<Coordinate> = VecDeque::from([*initial_location]);
+		// The risk of the target itself is weighed by the caller, only the tiles on the way are charged.
+		if location == target {
+			return Some(0);
+		}
 
-	while let Some(current_location) = queue.pop_front() {
-		for new_location in current_location.get_neighbours() {
+		// Discovered locations are only penalized by their known hazards.
+		if map.discovered.contains(location) {
+			let mut cost = 0;
+			if map.wumpuses.contains(location) { cost -= rules.score_wumpus; }
+			if map.pits.contains(location)     { cost -= rules.score_pit; }
+			return Some(cost);
+		}
 
-			// Ignore locations outside the map.
-			if ! map.encompass(&new_location) {
-				continue;
-			}
+		// Undiscovered locations are penalized by the expected score lost when walking into them.
+		let class = classes.get(location)?;
+		let risk = class.wumpus * -rules.score_wumpus as f64 + class.pit * -rules.score_pit as f64;
+		return Some(risk.round() as i32);
+	}
+}
 
-			// If this location is available and hasn't been evaluated yet, add to queue.
-			if map.discovered.contains(&new_location) && ! links.contains_key(&new_location) {
-				queue.push_back(new_location);
-			}
 
-			// Get the currently known cost of getting to the location.
-			let known_cost = *costs
-				.get(&new_location)
-				.unwrap_or(&std::i32::MAX)
-				;
-
-			// Calculate the new cost of getting to the location.
-			let mut new_cost: i32 = costs[&current_location];
-			if map.wumpuses.contains(&new_location) { new_cost -= Game::SCORE_WUMPUS; }
-			if map.pits.contains(&new_location) { new_cost -= Game::SCORE_PIT; }
-
-			let relative_direction = current_location.get_relative_direction(&new_location).unwrap();
-			if relative_direction == dirs[&current_location].rotate_right() { new_cost += 1; }
-			if relative_direction == dirs[&current_location].rotate_left() { new_cost += 1; }
-			if relative_direction == dirs[&current_location].rotate_back() { new_cost += 2; }
-			new_cost += 1;
-
-			// If the new cost is lower, set this path as the preferred one.
-			if new_cost < known_cost {
-				links.insert(new_location, current_location);
-				dirs.insert(new_location, relative_direction);
-				costs.insert(new_location, new_cost);
-			}
-		}
-	}
+fn pathfind_heuristic(location: &Coordinate, direction: &Direction, target: &Coordinate, rules: &Rules) -> i32 {
+
+	// Every remaining step needs at least one walk.
+	let dx = target.x - location.x;
+	let dy = target.y - location.y;
+	let walks = dx.abs() + dy.abs();
 
-	return (links, costs);
+	// Every axis that needs to be travelled along needs to be faced at some point.
+	let mut required: Vec<Direction> = Vec::with_capacity(2);
+	if dx > 0 { required.push(Direction::East);  }
+	if dx < 0 { required.push(Direction::West);  }
+	if dy > 0 { required.push(Direction::North); }
+	if dy < 0 { required.push(Direction::South); }
+
+	let turns = match required.as_slice() {
+		[] => 0,
+		[d] if d == direction => 0,
+		[d] if d.rotate_back() == *direction => 2,
+		[_] => 1,
+		ds if ds.contains(direction) => 1,
+		_ => 2,
+	};
+
+	return (walks + turns) * -rules.score_action;
 }
 
 
-pub fn path_to_actions(target: &Coordinate, initial_direction: &Direction, pathmap: &HashMap<Coordinate, Coordinate>) -> Option<Vec<Action>> {
+pub fn pathfind(
+	initial_location: &Coordinate,
+	initial_direction: &Direction,
+	target: &Coordinate,
+	rules: &Rules,
+	cost: impl Fn(&Coordinate) -> Option<i32>,
+) -> Option<(i32, Vec<Action>)> {
 
-	// If no path to the location is known, return None.
-	if ! pathmap.contains_key(&target) {
-		return None;
-	}
+	type State = (Coordinate, Direction);
+
+	let initial_state: State = (*initial_location, *initial_direction);
+	let mut links: HashMap<State, (State, Action)> = Default::default();
+	let mut costs: HashMap<State, i32> = HashMap::from([(initial_state, 0)]);
 
-	let mut actions: Vec<Action> = Default::default();
+	let mut queue: BinaryHeap<Reverse<(i32, i32, State)>> = BinaryHeap::new();
+	queue.push(Reverse((pathfind_heuristic(initial_location, initial_direction, target, rules), 0, initial_state)));
 
-	// Build the chain of locations to get to the target.
-	let mut path: Vec<Coordinate> = vec![*target];
+	while let Some(Reverse((_, current_cost, current_state))) = queue.pop() {
+		let (current_location, current_direction) = current_state;
 
-	let mut location = target;
-	while let Some(new_location) = pathmap.get(location) {
-		if new_location != location {
-			path.push(*new_location);
-			location = new_location;
+		// Skip stale queue entries which have since been improved upon.
+		if current_cost > costs[&current_state] {
+			continue;
 		}
-		else {
-			break;
+
+		// Once the target is reached, walk the links backwards to build the actions.
+		if current_location == *target {
+			let mut actions: Vec<Action> = Default::default();
+			let mut state = current_state;
+			while let Some(&(previous_state, action)) = links.get(&state) {
+				actions.push(action);
+				state = previous_state;
+			}
+			actions.reverse();
+			return Some((current_cost, actions));
+		}
+
+		// Every action costs the same, walking also costs whatever the new location costs.
+		let front_location = current_location.get_front(&current_direction);
+		let mut transitions: Vec<(State, Action, i32)> = vec![
+			((current_location, current_direction.rotate_left()),  Action::Left,  -rules.score_action),
+			((current_location, current_direction.rotate_right()), Action::Right, -rules.score_action),
+		];
+		if let Some(front_cost) = cost(&front_location) {
+			transitions.push(((front_location, current_direction), Action::Walk, -rules.score_action + front_cost));
+		}
+
+		// If the new cost is lower, set this path as the preferred one.
+		for (new_state, action, step_cost) in transitions {
+			let new_cost = current_cost + step_cost;
+			if new_cost < *costs.get(&new_state).unwrap_or(&i32::MAX) {
+				let (new_location, new_direction) = new_state;
+				links.insert(new_state, (current_state, action));
+				costs.insert(new_state, new_cost);
+				queue.push(Reverse((new_cost + pathfind_heuristic(&new_location, &new_direction, target, rules), new_cost, new_state)));
+			}
 		}
-	}
-	path.reverse();
-
-	// Turn the location chain into actions.
-	let mut direction: Direction = *initial_direction;
-	let mut location: Coordinate = path[0];
-	for new_location in path {
-		if new_location == location { continue; }
-
-		let new_direction = location.get_relative_direction(&new_location).unwrap();
-		if new_direction == direction.rotate_back()  { actions.push(Action::Right);  actions.push(Action::Right); }
-		if new_direction == direction.rotate_right() { actions.push(Action::Right); }
-		if new_direction == direction.rotate_left()  { actions.push(Action::Left); }
-
-		location = new_location;
-		direction = new_direction;
-		actions.push(Action::Walk);
 	}
 
-	return Some(actions);
+	return None;
 }
 
 
@@ -290,11 +315,96 @@ pub fn visualize_map(map: &Map, player_location: &Coordinate, player_direction:
 }
 
 
+pub fn visualize_probabilities(
+	map: &Map,
+	player_location: &Coordinate,
+	player_direction: &Direction,
+	probabilities: &HashMap<Coordinate, ClassField<f64>>,
+	color: &bool,
+) -> String {
+
+	const SEPARATOR_X: &str = "    ";
+	const SEPARATOR_Y: &str = "\n";
+	const COLOR_RESET: &str = "\x1b[0m";
+	let mut minimap = String::new();
+
+	// Print a probability as a class letter and a percentage, shaded by how likely it is.
+	let push_probability = |minimap: &mut String, letter: char, probability: f64, hue: u8| {
+		let percent = (probability * 100.0).round() as i32;
+		let text = format!("{letter}{percent:>3}");
+		if *color && percent > 0 {
+			let weight = if percent >= 50 { 1 } else { 2 };
+			minimap.push_str(&format!("\x1b[{weight};{hue}m{text}{COLOR_RESET}"));
+		}
+		else {
+			minimap.push_str(&text);
+		}
+	};
+
+	minimap.push_str(SEPARATOR_Y);
+	for y in (0..=map.size.y).rev() {
+
+		// Print the treasure, wumpus and pit probability on one line each
+		for (line, letter) in ['T', 'W', 'P'].into_iter().enumerate() {
+			for x in 0..=map.size.x {
+				minimap.push_str(SEPARATOR_X);
+
+				let location = Coordinate{x, y};
+				if let Some(class) = probabilities.get(&location) {
+					match letter {
+						'T' => push_probability(&mut minimap, letter, class.treasure, 33),
+						'W' => push_probability(&mut minimap, letter, class.wumpus, 31),
+						_   => push_probability(&mut minimap, letter, class.pit, 31),
+					}
+					continue;
+				}
+
+				if ! map.discovered.contains(&location) {
+					minimap.push_str("xxxx");
+					continue;
+				}
+
+				// Print the known class on this location
+				let known = match letter {
+					'T' => map.treasures.contains(&location),
+					'W' => map.wumpuses.contains(&location),
+					_   => map.pits.contains(&location),
+				};
+
+				// Print the player location and direction on discovered locations
+				if line == 0 && location == *player_location {
+					minimap.push(match player_direction {
+						Direction::North => '^',
+						Direction::East  => '>',
+						Direction::South => 'v',
+						Direction::West  => '<',
+					});
+				}
+				else if known {
+					minimap.push(letter);
+				}
+				else {
+					minimap.push('-');
+				}
+				minimap.push_str(if known { "100" } else { "---" });
+			}
+			minimap.push('\n');
+		}
+
+		minimap.push_str(SEPARATOR_Y);
+	}
+
+	minimap.pop();
+	return minimap;
+}
+
+
 pub fn calculate_map_possibilities(
 	frontier: &[Coordinate],
 	possible_treasures: &[Coordinate],
 	map: &Map,
 	blacklist: &HashMap<Coordinate, Class>,
+	rules: &Rules,
 ) -> (i32, HashMap<Coordinate, ClassField<i32>>) {
 
 	let length = frontier.len() + possible_treasures.len();
@@ -316,7 +426,7 @@ pub fn calculate_map_possibilities(
 			tmp_map.apply_classes(&locations, &classes);
 
 			// Verify that the permutation upholds the game logic.
-			if ! is_map_valid(&tmp_map, &blacklist) {
+			if ! is_map_valid(&tmp_map, blacklist, rules) {
 				continue;
 			}
 
@@ -342,3 +452,159 @@ pub fn calculate_map_possibilities(
 	return (total_possibilities, counts)
 }
 
+
+// ---
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	use rand::{Rng, SeedableRng, rngs::StdRng};
+
+	// The cost of walking onto every tile of a 4x4 cave, with the tiles which can't be entered left out.
+	fn grid(costs: HashMap<Coordinate, i32>, walls: &[Coordinate]) -> impl Fn(&Coordinate) -> Option<i32> + '_ {
+		move |location: &Coordinate| {
+			let inside = (0..4).contains(&location.x) && (0..4).contains(&location.y);
+			(inside && ! walls.contains(location)).then(|| costs.get(location).copied().unwrap_or(0))
+		}
+	}
+
+	// The cost of the cheapest path by uniform cost search, without the heuristic.
+	fn cheapest(location: Coordinate, direction: Direction, target: Coordinate, rules: &Rules, cost: &impl Fn(&Coordinate) -> Option<i32>) -> Option<i32> {
+		let mut costs: HashMap<(Coordinate, Direction), i32> = HashMap::from([((location, direction), 0)]);
+		let mut queue = BinaryHeap::from([Reverse((0, location, direction))]);
+		while let Some(Reverse((current_cost, location, direction))) = queue.pop() {
+			if location == target {
+				return Some(current_cost);
+			}
+			let front = location.get_front(&direction);
+			let mut next = vec![
+				(location, direction.rotate_left(), -rules.score_action),
+				(location, direction.rotate_right(), -rules.score_action),
+			];
+			if let Some(front_cost) = cost(&front) {
+				next.push((front, direction, -rules.score_action + front_cost));
+			}
+			for (location, direction, step_cost) in next {
+				let new_cost = current_cost + step_cost;
+				if new_cost < *costs.get(&(location, direction)).unwrap_or(&i32::MAX) {
+					costs.insert((location, direction), new_cost);
+					queue.push(Reverse((new_cost, location, direction)));
+				}
+			}
+		}
+		return None;
+	}
+
+	#[test]
+	fn pathfinds_to_the_start() {
+		let rules = Rules::default();
+		let start = Coordinate{x: 1, y: 1};
+		assert_eq!(pathfind(&start, &Direction::North, &start, &rules, grid(HashMap::new(), &[])), Some((0, Vec::new())));
+	}
+
+	#[test]
+	fn pays_for_turning() {
+		let rules = Rules::default();
+		let step = -rules.score_action;
+		let start = Coordinate{x: 0, y: 0};
+		let path = |target: Coordinate, direction: Direction| pathfind(&start, &direction, &target, &rules, grid(HashMap::new(), &[]));
+
+		assert_eq!(path(Coordinate{x: 3, y: 0}, Direction::East), Some((3 * step, vec![Action::Walk; 3])));
+		assert_eq!(path(Coordinate{x: 0, y: 2}, Direction::East), Some((3 * step, vec![Action::Left, Action::Walk, Action::Walk])));
+
+		// Facing away from the target takes two turns, whichever way they go.
+		let (cost, actions) = path(Coordinate{x: 1, y: 0}, Direction::West).unwrap();
+		assert_eq!(cost, 3 * step);
+		assert_eq!(actions.len(), 3);
+		assert_eq!(actions.last(), Some(&Action::Walk));
+	}
+
+	#[test]
+	fn avoids_costly_tiles() {
+		let rules = Rules::default();
+		let step = -rules.score_action;
+		let start = Coordinate{x: 0, y: 0};
+		let target = Coordinate{x: 2, y: 0};
+		let costs = HashMap::from([(Coordinate{x: 1, y: 0}, 100)]);
+
+		// Going around the costly tile takes 4 more walks and 3 turns, which is cheaper than walking through it.
+		let (cost, actions) = pathfind(&start, &Direction::East, &target, &rules, grid(costs, &[])).unwrap();
+		assert_eq!(cost, 7 * step);
+		assert_eq!(actions.iter().filter(|&&action| action == Action::Walk).count(), 4);
+	}
+
+	#[test]
+	fn fails_on_unreachable_targets() {
+		let rules = Rules::default();
+		let start = Coordinate{x: 0, y: 0};
+		let walls = [Coordinate{x: 2, y: 3}, Coordinate{x: 2, y: 2}, Coordinate{x: 3, y: 2}];
+		assert_eq!(pathfind(&start, &Direction::East, &Coordinate{x: 3, y: 3}, &rules, grid(HashMap::new(), &walls)), None);
+		assert_eq!(pathfind(&start, &Direction::East, &Coordinate{x: 5, y: 0}, &rules, grid(HashMap::new(), &[])), None);
+	}
+
+	#[test]
+	fn charges_the_risk_of_the_tiles_on_the_way() {
+		let rules = Rules::default();
+		let mut map: Map = ". . . .\n. . . .\nW . . T\nS . P .".parse().unwrap();
+		map.discovered = [Coordinate{x: 0, y: 0}, Coordinate{x: 0, y: 1}, Coordinate{x: 2, y: 0}].into();
+		let target = Coordinate{x: 3, y: 0};
+		let classes = HashMap::from([
+			(Coordinate{x: 1, y: 0}, ClassField{empty: 0.25, treasure: 0.0, wumpus: 0.25, pit: 0.5}),
+			(target, ClassField{empty: 0.0, treasure: 0.0, wumpus: 0.5, pit: 0.5}),
+		]);
+		let cost = risk_cost(&map, &rules, &classes, &target);
+
+		// Undiscovered tiles are charged the expected loss, the target nothing, whatever its risk.
+		let expected = (0.25 * -rules.score_wumpus as f64 + 0.5 * -rules.score_pit as f64).round() as i32;
+		assert_eq!(cost(&Coordinate{x: 1, y: 0}), Some(expected));
+		assert_eq!(cost(&target), Some(0));
+
+		// Discovered tiles are charged their known hazards, and tiles without beliefs or outside the map can't be entered.
+		assert_eq!(cost(&Coordinate{x: 0, y: 0}), Some(0));
+		assert_eq!(cost(&Coordinate{x: 0, y: 1}), Some(-rules.score_wumpus));
+		assert_eq!(cost(&Coordinate{x: 2, y: 0}), Some(-rules.score_pit));
+		assert_eq!(cost(&Coordinate{x: 1, y: 1}), None);
+		assert_eq!(cost(&Coordinate{x: -1, y: 0}), None);
+
+		// So the path to the target through the uncertain tile costs its risk, besides the actions.
+		let (path_cost, _) = pathfind(&Coordinate{x: 1, y: 0}, &Direction::East, &target, &rules, &cost).unwrap();
+		assert_eq!(path_cost, 2 * -rules.score_action - rules.score_pit);
+	}
+
+	#[test]
+	fn finds_the_cheapest_paths() {
+		let rules = Rules::default();
+		let mut rng = StdRng::seed_from_u64(0);
+		let directions = [Direction::East, Direction::South, Direction::West, Direction::North];
+		let random_location = |rng: &mut StdRng| Coordinate{x: rng.gen_range(0..4), y: rng.gen_range(0..4)};
+
+		for _ in 0..200 {
+			let costs: HashMap<Coordinate, i32> = (0..6).map(|_| (random_location(&mut rng), rng.gen_range(0..20))).collect();
+			let walls: Vec<Coordinate> = (0..3).map(|_| random_location(&mut rng)).collect();
+			let start = random_location(&mut rng);
+			let target = random_location(&mut rng);
+			let direction = directions[rng.gen_range(0..4)];
+			let cost = grid(costs, &walls);
+
+			let path = pathfind(&start, &direction, &target, &rules, &cost);
+			assert_eq!(path.as_ref().map(|(cost, _)| *cost), cheapest(start, direction, target, &rules, &cost));
+
+			// The actions lead to the target, and cost what was reported.
+			let Some((path_cost, actions)) = path else { continue };
+			let (mut location, mut direction, mut total) = (start, direction, 0);
+			for action in actions {
+				total -= rules.score_action;
+				match action {
+					Action::Left => direction = direction.rotate_left(),
+					Action::Right => direction = direction.rotate_right(),
+					Action::Walk => { location = location.get_front(&direction); total += cost(&location).unwrap(); },
+					_ => panic!("Unexpected action {action}"),
+				}
+			}
+			assert_eq!((location, total), (target, path_cost));
+		}
+	}
+
+}
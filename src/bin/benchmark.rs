@@ -0,0 +1,104 @@
+use wumpusworld::wumpus;
+use wumpusworld::models;
+
+
+const USAGE: &str = "\
+Usage: benchmark [GAMES | PATH]
+
+Plays the same maps with every exploration strategy of the bayes model, and reports the mean score
+and win rate of each.
+
+Arguments:
+  GAMES                  The number of seeded maps to play [default: 1000]
+  PATH                   Play on the maps of a map file, or of every .txt or .map file in a directory, instead
+
+Options:
+  -h, --help             Print this help text";
+
+
+struct Options {
+	games: Vec<wumpus::Game>,
+}
+
+impl Options {
+
+	// Parses the command line arguments, returning None if help was requested.
+	fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+
+		// Select the number of seeded games to play, or the maps to play on.
+		let seeded = |count: u64| (0..count)
+			.map(|seed| wumpus::Game::new_seeded(&Default::default(), seed))
+			.collect();
+		let games: Vec<wumpus::Game> = match args.next() {
+			None => seeded(1000),
+			Some(arg) if arg == "-h" || arg == "--help" => return Ok(None),
+			Some(arg) if arg.starts_with('-') => return Err(format!("Unknown option: {arg}")),
+			Some(arg) => match arg.parse::<u64>() {
+				Ok(count) => seeded(count),
+				Err(_) => {
+					let rules = wumpus::Rules::default();
+					let maps = wumpus::Map::load_all(&arg)?;
+					rules.validate_maps(&maps)?;
+					maps
+						.into_iter()
+						.map(|(_, map)| wumpus::Game::from_map(&rules.with_map(&map), map))
+						.collect()
+				},
+			},
+		};
+		if let Some(arg) = args.next() {
+			return Err(format!("Unexpected argument: {arg}"));
+		}
+		if games.is_empty() {
+			return Err("At least one game is required".to_string());
+		}
+		return Ok(Some(Self{games}));
+	}
+
+}
+
+
+fn main() {
+
+	let options = match Options::parse(std::env::args().skip(1)) {
+		Ok(Some(options)) => options,
+		Ok(None) => {
+			println!("{USAGE}");
+			return;
+		},
+		Err(message) => {
+			eprintln!("Error: {message}");
+			eprintln!("Run with --help for usage.");
+			std::process::exit(2);
+		},
+	};
+	let games = options.games;
+	let game_count = games.len();
+
+	const MAX_ACTIONS: usize = 1000;
+	let strategies = [
+		("heuristic", models::Exploration::Heuristic),
+		("information", models::Exploration::Information),
+	];
+
+	println!("Playing {game_count} games per exploration strategy...");
+	for (name, exploration) in strategies {
+
+		let mut total_score: i64 = 0;
+		let mut wins: u64 = 0;
+
+		let params = models::ParamsBayes{exploration, .. Default::default()};
+		let mut model = models::ModelBayes{params, .. Default::default()};
+
+		// Play the same maps with every strategy.
+		for game in &games {
+			let game = models::play(&mut model, game.clone(), MAX_ACTIONS);
+			total_score += game.score as i64;
+			if game.game_over && ! game.events.wumpus { wins += 1; }
+		}
+
+		println!("{name:>12}: mean score {:.2}, won {wins}/{game_count}",
+			total_score as f64 / game_count as f64,
+		);
+	}
+}
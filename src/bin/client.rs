@@ -2,72 +2,567 @@
 use wumpusworld::wumpus;
 use wumpusworld::models;
 use wumpusworld::algorithms;
+use wumpusworld::tui;
+use wumpusworld::protocol::{self, Message, Mode};
+use wumpusworld::multiplayer::{PlayerStatus, TeamMessage};
+use wumpusworld::websocket::WebSocket;
+
+use std::io::Write;
+use std::time::Duration;
 
 use serde::Deserialize;
 
 
-fn main() {
+const CLIENT_NAME: &str = "wumpusworld-client";
 
-	// Select model to use.
-	let model = std::env::args()
-		.nth(1)
-		.expect("No model specified")
-		.to_lowercase()
-		;
-	let mut model: Box<dyn models::Model> = match model.as_str() {
-		"random" => Box::new(models::ModelRandom{}),
-		"manual" => Box::new(models::ModelManual{}),
-		"bayes" => Box::new(models::ModelBayes{.. Default::default()}),
-		_ => panic!("Unknown model type"),
-	};
+const USAGE: &str = "\
+Usage: client [OPTIONS] [SERVER] MODEL
+       client --leaderboard N [SERVER]
+       client --spectate ID [SERVER]
+
+Connects to a WumpusWorld server and plays using the given model.
+
+Models:
+  manual                 Play the game manually
+  random                 Perform random actions
+  bayes                  Play using bayesian statistics
+
+Options:
+  --server ADDRESS       The server to connect to, or a ws:// URL to use a WebSocket [default: 127.0.0.1:6666]
+  --model MODEL          The model to play with
+  --name NAME            The name shown on the leaderboard of the server
+  --mode MODE            Play alone (single), or share a cave with other players (competitive or cooperative) [default: single]
+  --leaderboard N        Print the N best scores of the server and exit, those of matches along with --mode
+  --spectate ID          Watch the session of another player, with the map revealed
+  --seed SEED            Generate the map from a seed, incremented for every game
+  --daily                Play the daily challenge, the same map for everyone today, or show its
+                         leaderboard along with --leaderboard
+  --games N              The number of games to play in a row [default: 1]
+  --delay-ms MS          The delay between actions in milliseconds [default: 1000]
+  --quiet                Only print the final score of every game
+  --log FILE             Write every game state and action to FILE as JSON lines
+  --replay FILE          Save the replay of the game to FILE, numbered when playing several games
+  --params FILE          Load the model parameters from a JSON file
+  --param KEY=VALUE      Override a single model parameter
+  --explain              Print the reasoning behind every action
+  --explain-log FILE     Write the reasoning behind every action to FILE as JSON lines
+  --heatmap              Print the class probabilities believed by the model
+  --color                Shade the heatmap using ANSI colours
+  --tui                  Play in a full-screen terminal interface
+  -h, --help             Print this help text";
+
+
+struct Options {
+	server      : String,
+	model       : String,
+	name        : Option<String>,
+	mode        : Mode,
+	leaderboard : Option<usize>,
+	spectate    : Option<u64>,
+	seed        : Option<u64>,
+	daily       : bool,
+	games       : u64,
+	delay       : Duration,
+	quiet       : bool,
+	log         : Option<std::fs::File>,
+	replay      : Option<String>,
+	params_file : Option<String>,
+	params      : Vec<String>,
+	explain     : bool,
+	explain_log : Option<std::fs::File>,
+	heatmap     : bool,
+	color       : bool,
+	tui         : bool,
+}
+
+impl Options {
+
+	// Parses the command line arguments, returning None if help was requested.
+	fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+
+		let mut options = Self {
+			server      : "127.0.0.1:6666".to_string(),
+			model       : String::new(),
+			name        : None,
+			mode        : Mode::Single,
+			leaderboard : None,
+			spectate    : None,
+			seed        : None,
+			daily       : false,
+			games       : 1,
+			delay       : Duration::from_secs(1),
+			quiet       : false,
+			log         : None,
+			replay      : None,
+			params_file : None,
+			params      : Vec::new(),
+			explain     : false,
+			explain_log : None,
+			heatmap     : false,
+			color       : false,
+			tui         : false,
+		};
+
+		let mut positionals: Vec<String> = Vec::new();
+		while let Some(arg) = args.next() {
+
+			let mut value = || args
+				.next()
+				.ok_or(format!("Missing value for {arg}"));
+			let create = |path: String| std::fs::File::create(&path)
+				.map_err(|e| format!("Unable to create {path}: {e}"));
+
+			match arg.as_str() {
+				"--server"      => options.server = value()?,
+				"--model"       => options.model = value()?,
+				"--name"        => options.name = Some(value()?),
+				"--mode"        => options.mode = value()?.parse()?,
+				"--leaderboard" => options.leaderboard = Some(value()?.parse().map_err(|_| "Invalid entry count")?),
+				"--spectate"    => options.spectate = Some(value()?.parse().map_err(|_| "Invalid session id")?),
+				"--seed"        => options.seed = Some(value()?.parse().map_err(|_| "Invalid seed")?),
+				"--daily"       => options.daily = true,
+				"--games"       => options.games = value()?.parse().map_err(|_| "Invalid game count")?,
+				"--delay-ms"    => options.delay = Duration::from_millis(value()?.parse().map_err(|_| "Invalid delay")?),
+				"--quiet"       => options.quiet = true,
+				"--log"         => options.log = Some(create(value()?)?),
+				"--replay"      => options.replay = Some(value()?),
+				"--params"      => options.params_file = Some(value()?),
+				"--param"       => options.params.push(value()?),
+				"--explain"     => options.explain = true,
+				"--explain-log" => options.explain_log = Some(create(value()?)?),
+				"--heatmap"     => options.heatmap = true,
+				"--color"       => options.color = true,
+				"--tui"         => options.tui = true,
+				"-h" | "--help" => return Ok(None),
+				_ if arg.starts_with('-') => return Err(format!("Unknown option: {arg}")),
+				_ => positionals.push(arg),
+			}
+		}
+
+		// Positional arguments are given as `[SERVER] MODEL`.
+		match positionals.as_slice() {
+			[] => (),
+			[server] if options.leaderboard.is_some() || options.spectate.is_some() => options.server = server.clone(),
+			[model] => options.model = model.clone(),
+			[server, model] => { options.server = server.clone(); options.model = model.clone(); },
+			_ => return Err("Too many arguments".to_string()),
+		}
+
+		if options.daily && options.seed.is_some() {
+			return Err("The daily challenge has its own seed".to_string());
+		}
+		if options.model.is_empty() && options.leaderboard.is_none() && options.spectate.is_none() {
+			return Err("No model specified".to_string());
+		}
+		options.model = options.model.to_lowercase();
+		return Ok(Some(options));
+	}
+
+
+	fn create_model(&self) -> Result<Box<dyn models::Model>, String> {
+		let has_params = self.params_file.is_some() || ! self.params.is_empty();
+		match self.model.as_str() {
+			"random" | "manual" if has_params => Err(format!("The {} model takes no parameters", self.model)),
+			"random" => Ok(Box::new(models::ModelRandom::default())),
+			"manual" => Ok(Box::new(models::ModelManual::default())),
+			"bayes" => {
+				let mut params: models::ParamsBayes = match &self.params_file {
+					Some(path) => models::load_params(path)?,
+					None => Default::default(),
+				};
+				for assignment in &self.params {
+					models::set_param(&mut params, assignment)?;
+				}
+				Ok(Box::new(models::ModelBayes{params, .. Default::default()}))
+			},
+			_ => Err(format!("Unknown model: {}", self.model)),
+		}
+	}
+
+}
 
-	// Connect to the game server.
-	let stream = std::net::TcpStream::connect(concat!("127.0.0.1:", 6666)).expect("Unable to connect to the server");
-	let mut de = serde_json::Deserializer::from_reader(&stream);
+
+// A connection to the server, carrying the JSON messages as a raw stream or over a WebSocket.
+enum Connection {
+	Tcp(serde_json::Deserializer<serde_json::de::IoRead<std::net::TcpStream>>, std::net::TcpStream),
+	WebSocket(WebSocket),
+}
+
+impl Connection {
+
+	fn open(server: &str) -> Result<Self, String> {
+		if server.starts_with("ws://") {
+			return Ok(Self::WebSocket(WebSocket::connect(server)?));
+		}
+		let stream = std::net::TcpStream::connect(server)
+			.map_err(|e| format!("Unable to connect to {server}: {e}"))?;
+		let reader = stream.try_clone().map_err(|e| format!("Unable to connect to {server}: {e}"))?;
+		return Ok(Self::Tcp(serde_json::Deserializer::from_reader(reader), stream));
+	}
+
+
+	fn send(&mut self, message: &Message) -> Result<(), String> {
+		let result = match self {
+			Self::Tcp(_, stream) => serde_json::to_writer(stream, message).map_err(|e| e.to_string()),
+			Self::WebSocket(socket) => serde_json::to_string(message)
+				.map_err(|e| e.to_string())
+				.and_then(|text| socket.send_text(&text).map_err(|e| e.to_string())),
+		};
+		return result.map_err(|e| format!("Error while sending to the server: {e}"));
+	}
+
+
+	fn receive(&mut self) -> Result<Message, String> {
+		let message = match self {
+			Self::Tcp(de, _) => Message::deserialize(de).map_err(|e| e.to_string()),
+			Self::WebSocket(socket) => match socket.receive_text() {
+				Ok(Some(text)) => serde_json::from_str(&text).map_err(|e| e.to_string()),
+				Ok(None) => Err("The WebSocket was closed".to_string()),
+				Err(e) => Err(e.to_string()),
+			},
+		};
+		match message {
+			Ok(Message::Error{code, message}) if code.is_fatal() => Err(format!("The server disconnected ({code:?}): {message}")),
+			Ok(message) => Ok(message),
+			Err(e) => Err(format!("Error while receiving from the server: {e}")),
+		}
+	}
+
+}
+
+
+// Plays a single game, returning the final state, or None if the player quit.
+fn play_game(
+	options: &mut Options,
+	model: &mut dyn models::Model,
+	tui: &mut Option<tui::Tui>,
+	game_number: u64,
+	seed: Option<u64>,
+) -> Result<Option<wumpus::Game>, String> {
+
+	let manual = options.model == "manual";
+
+	// Connect to the game server, greet it and request a game.
+	let mut connection = Connection::open(&options.server)?;
+	connection.send(&Message::hello(CLIENT_NAME, options.name.as_deref(), &options.model, seed, options.mode, options.daily))?;
+	let Message::Welcome{..} = connection.receive()? else {
+		return Err("Expected a Welcome message from the server".to_string());
+	};
 
 	let mut game: wumpus::Game;
+	let mut last_action: Option<wumpus::Action> = None;
+	let mut inbox: Vec<TeamMessage> = Vec::new();
+	model.new_game();
 	loop {
 
 		// Receive the state of the game.
-		game = wumpus::Game::deserialize(&mut de).expect("Error while deserializing game from server");
+		let others;
+		(game, others) = match connection.receive()? {
+			Message::State{game, others} => (game, others),
+			Message::Lobby{players, needed} => {
+				let status = format!("Waiting for a match: {} ({}/{needed})", players.join(", "), players.len());
+				match tui {
+					Some(tui) => tui.log(&status),
+					None if ! options.quiet => println!("{status}"),
+					None => (),
+				}
+				continue;
+			},
+			Message::Chat{message} => {
+				let text = format!("{}: {}", message.from, message.text);
+				match tui {
+					Some(tui) => tui.log(&text),
+					None if ! options.quiet => println!("{text}"),
+					None => (),
+				}
+				inbox.push(message);
+				continue;
+			},
+			Message::Error{code, message} => {
+				let warning = format!("The server rejected the last message ({code:?}): {message}");
+				match tui {
+					Some(tui) => tui.log(&warning),
+					None => eprintln!("Warning: {warning}"),
+				}
+				continue;
+			},
+			_ => return Err("Expected a State message from the server".to_string()),
+		};
+
+		// Let the model observe the outcome of its last action.
+		if let Some(action) = last_action {
+			model.observe(&action, &game);
+		}
 
-		// Print the game and events.
-		println!("{}", algorithms::visualize_map(&game.map, &game.location, &game.direction, &false));
-		if game.events.bonked   { println!("> You hit your head against the wall. Ouch!"); }
-		if game.events.scream   { println!("> A terrible scream echoes throughout the cave..."); }
-		if game.events.treasure { println!("> You found a treasure! Congratulations!"); }
-		if game.events.pit      { println!("> Oh no, you fell into a pit :("); }
+		// Describe the events and ending game statement, along with the other players sharing the cave.
+		let mut messages: Vec<String> = game.describe_events().into_iter().map(str::to_string).collect();
+		messages.extend(others.iter().map(describe_player));
+
+		// Show the game, events and status bar.
+		if let Some(tui) = tui {
+			if let Some(action) = last_action { tui.log(&format!("Performed: {action}")); }
+			messages.iter().for_each(|message| tui.log(message));
+			if game.game_over { tui.log("Press any key to continue."); }
+			tui.draw(&game, manual);
+		}
+		else if ! options.quiet {
+			println!("{}", algorithms::visualize_map(&game.map, &game.location, &game.direction, &false));
+			messages.iter().for_each(|message| println!("> {message}"));
+			if ! game.game_over {
+				println!("Position: {} facing {}, arrows: {}, score: {}",
+					game.location,
+					game.direction,
+					game.arrows,
+					game.score,
+				);
+			}
+		}
+
+		// Log the game state, along with the chosen action further down.
+		let mut log_entry = serde_json::json!({"game": game_number, "state": game, "action": null});
 
-		// Print ending game statement.
 		if game.game_over {
-			if game.events.wumpus {
-				println!("> You walked into a wumpus den. GG");
+			let Message::GameOver{result} = connection.receive()? else {
+				return Err("Expected a GameOver message from the server".to_string());
+			};
+			// Show what the daily challenge was hiding.
+			if options.daily {
+				let mut revealed = game.clone();
+				revealed.map = result.map.clone();
+				match tui {
+					Some(tui) => {
+						revealed.map.discovered = (0..=revealed.map.size.x)
+							.flat_map(|x| (0..=revealed.map.size.y).map(move |y| wumpus::Coordinate{x, y}))
+							.collect();
+						tui.log("The map of the daily challenge is revealed.");
+						tui.draw(&revealed, manual);
+					},
+					None if ! options.quiet => println!("{}", algorithms::visualize_map(&revealed.map, &game.location, &game.direction, &true)),
+					None => (),
+				}
+			}
+			if ! result.standings.is_empty() {
+				let standings = result.standings.iter().map(|player| format!("{} ({})", player.name, player.score)).collect::<Vec<_>>();
+				let status = format!("{} the match: {}", if result.won { "You won" } else { "You lost" }, standings.join(", "));
+				match tui {
+					Some(tui) => tui.log(&status),
+					None if ! options.quiet => println!("{status}"),
+					None => (),
+				}
 			}
-			else {
-				println!("> All treasures have been found. GG");
+			if let (Some(path), Some(replay)) = (&options.replay, result.replay) {
+				let path = match options.games {
+					1 => path.clone(),
+					_ => numbered_path(path, game_number + 1),
+				};
+				replay.save(&path)?;
 			}
-			break;
+			model.game_over(&game);
+			if let Some(log) = &mut options.log {
+				writeln!(log, "{log_entry}").map_err(|e| format!("Error while writing to log file: {e}"))?;
+			}
+			if let Some(tui) = tui { tui.wait_for_key(); }
+			return Ok(Some(game));
+		}
+
+		// Let the model choose an action, or read it from the keyboard when playing manually.
+		let team = options.mode == Mode::Cooperative;
+		if team {
+			model.observe_team(&others, &inbox);
+			inbox.clear();
+		}
+		let action = match tui {
+			Some(tui) if manual => match tui.read_action() {
+				Some(action) => action,
+				None => return Ok(None),
+			},
+			_ => model.run(&game),
+		};
+
+		if let Some(log) = &mut options.log {
+			log_entry["action"] = serde_json::json!(action);
+			writeln!(log, "{log_entry}").map_err(|e| format!("Error while writing to log file: {e}"))?;
+		}
+
+		// Show and log the reasoning behind the action.
+		if let Some(explanation) = model.explain() {
+			let printing = tui.is_none() && ! options.quiet;
+			if options.explain && printing {
+				println!("{explanation}");
+			}
+			if options.heatmap && printing {
+				let probabilities = explanation.beliefs
+					.iter()
+					.map(|belief| (belief.location, belief.probabilities))
+					.collect();
+				println!("{}", algorithms::visualize_probabilities(&game.map, &game.location, &game.direction, &probabilities, &options.color));
+			}
+			if let Some(log) = &mut options.explain_log {
+				let entry = serde_json::json!({"action": action, "explanation": explanation});
+				writeln!(log, "{entry}").map_err(|e| format!("Error while writing to log file: {e}"))?;
+			}
+		}
+
+		// Send that action to the server, after telling the team about it.
+		if let Some(message) = model.team_message().filter(|_| team) {
+			connection.send(&Message::Chat{message})?;
+		}
+		connection.send(&Message::Action{action})?;
+		last_action = Some(action);
+
+		// Give the player time to follow along.
+		match tui {
+			Some(tui) if ! manual => if ! tui.wait_for_step(&game) { return Ok(None); },
+			Some(_) => (),
+			None => std::thread::sleep(options.delay),
 		}
+	}
+}
+
+
+// Describes where another player of a match is.
+fn describe_player(player: &PlayerStatus) -> String {
+	match player.active {
+		true => format!("{} is at {} facing {}, score: {}", player.name, player.location, player.direction, player.score),
+		false => format!("{} is out, score: {}", player.name, player.score),
+	}
+}
+
+
+// Inserts a number before the extension of a path, e.g. `replay-2.json`.
+fn numbered_path(path: &str, number: u64) -> String {
+	let path = std::path::Path::new(path);
+	let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+	let name = match path.extension() {
+		Some(extension) => format!("{stem}-{number}.{}", extension.to_string_lossy()),
+		None => format!("{stem}-{number}"),
+	};
+	return path.with_file_name(name).to_string_lossy().into_owned();
+}
+
+
+// Prints the best scores of the server for the rules it plays with and the mode, or those of the daily challenge.
+fn show_leaderboard(server: &str, count: usize, daily: bool, mode: Mode) -> Result<(), String> {
+
+	let mut connection = Connection::open(server)?;
+	connection.send(&Message::LeaderboardQuery{count, rules: None, daily, mode})?;
+	let Message::Leaderboard{entries} = connection.receive()? else {
+		return Err("Expected a Leaderboard message from the server".to_string());
+	};
 
-		// Show the status bar.
-		println!("Position: {} facing {}, arrows: {}, score: {}",
-			game.location,
-			game.direction,
-			game.arrows,
-			game.score,
+	if entries.is_empty() {
+		println!("No games have been recorded yet.");
+		return Ok(());
+	}
+	println!("{:>4}  {:>6}  {:<20} {:<8} {:>7}  {:>20}", "Rank", "Score", "Player", "Model", "Actions", "Seed");
+	for (rank, entry) in entries.iter().enumerate() {
+		println!("{:>4}  {:>6}  {:<20} {:<8} {:>7}  {:>20}",
+			rank + 1,
+			entry.score,
+			entry.player,
+			entry.model,
+			entry.actions,
+			entry.seed.map_or("-".to_string(), |seed| seed.to_string()),
 		);
+	}
+	Ok(())
+}
+
+
+// Watches the session of another player, printing the revealed game after every action.
+fn spectate(server: &str, session_id: u64) -> Result<(), String> {
+
+	let mut connection = Connection::open(server)?;
+	connection.send(&Message::Spectate{version: protocol::VERSION, session_id})?;
+	loop {
+		match connection.receive()? {
+			Message::State{game, ..} => {
+				println!("{}", algorithms::visualize_map(&game.map, &game.location, &game.direction, &true));
+				game.describe_events().iter().for_each(|message| println!("> {message}"));
+				println!("Position: {} facing {}, arrows: {}, score: {}",
+					game.location,
+					game.direction,
+					game.arrows,
+					game.score,
+				);
+			},
+			Message::GameOver{result} => {
+				println!();
+				println!("GAME OVER");
+				println!("Final score of session {session_id}: {}", result.score);
+				return Ok(());
+			},
+			_ => return Err("Expected a State message from the server".to_string()),
+		}
+	}
+}
+
+
+fn run(mut options: Options) -> Result<(), String> {
+
+	if let Some(count) = options.leaderboard {
+		return show_leaderboard(&options.server, count, options.daily, options.mode);
+	}
+	if let Some(session_id) = options.spectate {
+		return spectate(&options.server, session_id);
+	}
 
-		// Let the model choose an action.
-		let action = model.run(&game);
+	let mut model = options.create_model()?;
+	let mut tui = match options.tui {
+		true => Some(tui::Tui::new(options.delay)?),
+		false => None,
+	};
 
-		// Send that action to the server.
-		serde_json::to_writer(&stream, &action).expect("Error while sending action to server");
-		std::thread::sleep(std::time::Duration::from_secs(1));
+	// Play the games, stopping early if the player quits.
+	let mut scores: Vec<i32> = Vec::new();
+	for game_number in 0..options.games {
+		let seed = options.seed.map(|seed| seed.wrapping_add(game_number));
+		match play_game(&mut options, model.as_mut(), &mut tui, game_number, seed) {
+			Ok(Some(game)) => scores.push(game.score),
+			Ok(None) => break,
+			Err(message) => {
+				drop(tui);
+				return Err(message);
+			},
+		}
 	}
 
-	// Print the final score.
-	println!();
-	println!("GAME OVER");
-	println!("Final score: {}", game.score);
+	// Restore the terminal before printing the result.
+	drop(tui);
+
+	// Print the final scores.
+	if ! options.quiet {
+		println!();
+		println!("GAME OVER");
+	}
+	for (game_number, score) in scores.iter().enumerate() {
+		println!("Final score of game {}: {score}", game_number + 1);
+	}
+	if scores.len() > 1 {
+		let mean = scores.iter().sum::<i32>() as f64 / scores.len() as f64;
+		println!("Mean score over {} games: {mean:.2}", scores.len());
+	}
+	Ok(())
 }
 
+
+fn main() {
+
+	let options = match Options::parse(std::env::args().skip(1)) {
+		Ok(Some(options)) => options,
+		Ok(None) => {
+			println!("{USAGE}");
+			return;
+		},
+		Err(message) => {
+			eprintln!("Error: {message}");
+			eprintln!("Run with --help for usage.");
+			std::process::exit(2);
+		},
+	};
+
+	if let Err(message) = run(options) {
+		eprintln!("Error: {message}");
+		std::process::exit(1);
+	}
+}
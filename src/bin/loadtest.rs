@@ -0,0 +1,216 @@
+
+use std::io::BufReader;
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use wumpusworld::models::{self, Model};
+use wumpusworld::protocol::{Message, Mode};
+
+use serde::Deserialize;
+
+
+const USAGE: &str = "\
+Usage: loadtest [OPTIONS]
+
+Opens many simulated clients against a WumpusWorld server at once, playing random actions, and
+measures the throughput of the server.
+
+Options:
+  --server ADDRESS       The server to connect to [default: 127.0.0.1:6666]
+  --clients N            The number of concurrent clients [default: 1000]
+  --threads N            The number of threads driving the clients [default: 8]
+  --max-actions N        Leave a game after this many actions [default: 100]
+  -h, --help             Print this help text";
+
+
+struct Options {
+	server      : String,
+	clients     : usize,
+	threads     : usize,
+	max_actions : usize,
+}
+
+impl Options {
+
+	// Parses the command line arguments, returning None if help was requested.
+	fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+
+		let mut options = Self {
+			server      : "127.0.0.1:6666".to_string(),
+			clients     : 1000,
+			threads     : 8,
+			max_actions : 100,
+		};
+
+		while let Some(arg) = args.next() {
+
+			let mut value = || args
+				.next()
+				.ok_or(format!("Missing value for {arg}"));
+
+			match arg.as_str() {
+				"--server"      => options.server = value()?,
+				"--clients"     => options.clients = value()?.parse().map_err(|_| "Invalid client count")?,
+				"--threads"     => options.threads = value()?.parse().map_err(|_| "Invalid thread count")?,
+				"--max-actions" => options.max_actions = value()?.parse().map_err(|_| "Invalid action count")?,
+				"-h" | "--help" => return Ok(None),
+				_ => return Err(format!("Unknown option: {arg}")),
+			}
+		}
+
+		if options.threads == 0 {
+			return Err("At least one thread is required".to_string());
+		}
+		return Ok(Some(options));
+	}
+
+}
+
+
+// A simulated client, waiting for its next message from the server.
+struct Client {
+	stream  : TcpStream,
+	de      : serde_json::Deserializer<serde_json::de::IoRead<BufReader<TcpStream>>>,
+	actions : usize,
+	sent_at : Instant,
+}
+
+#[derive(Default)]
+struct Stats {
+	connected  : usize,
+	failed     : usize, // Clients which couldn't connect, or were disconnected by an error.
+	finished   : usize, // Games played until the end.
+	abandoned  : usize, // Games left after the maximum number of actions.
+	actions    : usize,
+	latencies  : Vec<Duration>,
+}
+
+impl Stats {
+
+	fn merge(&mut self, other: Stats) {
+		self.connected += other.connected;
+		self.failed    += other.failed;
+		self.finished  += other.finished;
+		self.abandoned += other.abandoned;
+		self.actions   += other.actions;
+		self.latencies.extend(other.latencies);
+	}
+
+}
+
+
+// Connects the clients, then takes turns with each of them until all games are done.
+fn drive_clients(options: &Options, count: usize, first_seed: u64) -> Stats {
+
+	let mut stats = Stats::default();
+	let mut model = models::ModelRandom::default();
+
+	let mut clients: Vec<Client> = Vec::new();
+	for seed in first_seed..first_seed + count as u64 {
+		let connection = TcpStream::connect(&options.server).and_then(|stream| {
+			let reader = BufReader::new(stream.try_clone()?);
+			serde_json::to_writer(&stream, &Message::hello("wumpusworld-loadtest", None, "random", Some(seed), Mode::Single, false))?;
+			Ok(Client {
+				stream,
+				de      : serde_json::Deserializer::from_reader(reader),
+				actions : 0,
+				sent_at : Instant::now(),
+			})
+		});
+		match connection {
+			Ok(client) => clients.push(client),
+			Err(_) => stats.failed += 1,
+		}
+	}
+	stats.connected = clients.len();
+
+	// Every client has a single message in flight, so reading them in turn never blocks for long.
+	while ! clients.is_empty() {
+		clients.retain_mut(|client| {
+			loop {
+				let game = match Message::deserialize(&mut client.de) {
+					Ok(Message::Welcome{..}) => continue,
+					Ok(Message::State{game, ..}) => game,
+					Ok(Message::GameOver{..}) => {
+						stats.finished += 1;
+						return false;
+					},
+					Ok(_) | Err(_) => {
+						stats.failed += 1;
+						return false;
+					},
+				};
+				if client.actions > 0 {
+					stats.latencies.push(client.sent_at.elapsed());
+				}
+				if game.game_over {
+					continue;
+				}
+				if client.actions == options.max_actions {
+					stats.abandoned += 1;
+					return false;
+				}
+
+				let action = model.run(&game);
+				if serde_json::to_writer(&client.stream, &Message::Action{action}).is_err() {
+					stats.failed += 1;
+					return false;
+				}
+				client.actions += 1;
+				client.sent_at = Instant::now();
+				stats.actions += 1;
+				return true;
+			}
+		});
+	}
+
+	return stats;
+}
+
+
+fn main() {
+
+	let options = match Options::parse(std::env::args().skip(1)) {
+		Ok(Some(options)) => options,
+		Ok(None) => {
+			println!("{USAGE}");
+			return;
+		},
+		Err(message) => {
+			eprintln!("Error: {message}");
+			eprintln!("Run with --help for usage.");
+			std::process::exit(2);
+		},
+	};
+
+	println!("Playing with {} clients on {} threads against {}...", options.clients, options.threads, options.server);
+	let start = Instant::now();
+
+	// Spread the clients evenly over the threads.
+	let mut stats = Stats::default();
+	std::thread::scope(|scope| {
+		let mut handles = Vec::new();
+		let mut first_seed = 0;
+		for thread in 0..options.threads {
+			let count = options.clients / options.threads + usize::from(thread < options.clients % options.threads);
+			let options = &options;
+			handles.push(scope.spawn(move || drive_clients(options, count, first_seed)));
+			first_seed += count as u64;
+		}
+		for handle in handles {
+			stats.merge(handle.join().expect("A client thread panicked"));
+		}
+	});
+	let elapsed = start.elapsed();
+
+	// Print the results.
+	stats.latencies.sort();
+	let percentile = |p: f64| stats.latencies
+		.get(((stats.latencies.len() as f64 * p) as usize).min(stats.latencies.len().saturating_sub(1)))
+		.copied()
+		.unwrap_or_default();
+	println!("Connected:  {}/{} clients, {} failed", stats.connected, options.clients, stats.failed);
+	println!("Games:      {} finished, {} left after {} actions", stats.finished, stats.abandoned, options.max_actions);
+	println!("Actions:    {} in {:.2}s, {:.0} actions/s", stats.actions, elapsed.as_secs_f64(), stats.actions as f64 / elapsed.as_secs_f64());
+	println!("Latency:    median {:.2?}, 99th percentile {:.2?}, max {:.2?}", percentile(0.5), percentile(0.99), percentile(1.0));
+}
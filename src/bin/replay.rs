@@ -0,0 +1,241 @@
+
+use wumpusworld::wumpus;
+use wumpusworld::algorithms;
+use wumpusworld::tui::{self, Key};
+use wumpusworld::multiplayer::PlayerStatus;
+use wumpusworld::replay::{MatchReplay, Replay};
+
+use std::time::Duration;
+
+
+const USAGE: &str = "\
+Usage: replay [OPTIONS] FILE
+
+Steps forward and backward through the replay of a game or a match, showing the map, score and events
+after every action. The steps of a match are shown as the player who took them saw the cave.
+
+Options:
+  --step N               Start at this step, 0 being the initial state [default: 0]
+  --reveal               Start by showing the full map instead of the view of the player
+  --print                Print every step from the starting one, instead of browsing interactively
+  -h, --help             Print this help text
+
+Controls:
+  Right/D/Space          Next step
+  Left/A                 Previous step
+  Up/W                   First step
+  Down/S                 Last step
+  R                      Toggle between the view of the player and the full map
+  Q                      Quit";
+
+
+struct Options {
+	path   : String,
+	step   : usize,
+	reveal : bool,
+	print  : bool,
+}
+
+impl Options {
+
+	// Parses the command line arguments, returning None if help was requested.
+	fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+
+		let mut options = Self {
+			path   : String::new(),
+			step   : 0,
+			reveal : false,
+			print  : false,
+		};
+
+		while let Some(arg) = args.next() {
+
+			let mut value = || args
+				.next()
+				.ok_or(format!("Missing value for {arg}"));
+
+			match arg.as_str() {
+				"--step"        => options.step = value()?.parse().map_err(|_| "Invalid step")?,
+				"--reveal"      => options.reveal = true,
+				"--print"       => options.print = true,
+				"-h" | "--help" => return Ok(None),
+				_ if arg.starts_with('-') => return Err(format!("Unknown option: {arg}")),
+				_ if options.path.is_empty() => options.path = arg,
+				_ => return Err("Too many arguments".to_string()),
+			}
+		}
+
+		if options.path.is_empty() {
+			return Err("No replay file specified".to_string());
+		}
+		return Ok(Some(options));
+	}
+
+}
+
+
+// The state after a step of the replay, as seen by whoever took it.
+struct Frame {
+	action : String,            // The action which led to this state, along with who took it in a match.
+	game   : wumpus::Game,
+	change : i32,               // How much the action changed the score of whoever took it.
+	others : Vec<PlayerStatus>, // The other players of a match.
+}
+
+// Plays the replay of a game again, returning every state, starting with the initial one.
+fn game_frames(replay: &Replay) -> Result<Vec<Frame>, String> {
+	let games = replay.play()?;
+	let frames = games
+		.iter()
+		.enumerate()
+		.map(|(step, game)| Frame {
+			action : if step == 0 { "start".to_string() } else { replay.steps[step - 1].action.to_string() },
+			game   : game.clone(),
+			change : if step == 0 { 0 } else { game.score - games[step - 1].score },
+			others : Vec::new(),
+		})
+		.collect();
+	return Ok(frames);
+}
+
+// Plays the replay of a match again, returning the state after every turn, as seen by the player who took it.
+fn match_frames(replay: &MatchReplay) -> Result<Vec<Frame>, String> {
+	let games = replay.play()?;
+	let mut frames = vec![Frame{action: "start".to_string(), game: games[0].view(0), change: 0, others: games[0].others(0)}];
+	for (turn, states) in replay.turns.iter().zip(games.windows(2)) {
+		let (before, after) = (&states[0], &states[1]);
+		let player = turn.player;
+		frames.push(Frame {
+			action : format!("{}: {}", replay.players[player], turn.action.map_or("leave".to_string(), |action| action.to_string())),
+			game   : after.view(player),
+			change : after.explorers[player].score - before.explorers[player].score,
+			others : after.others(player),
+		});
+	}
+	return Ok(frames);
+}
+
+
+// Describes a single step of the replay.
+fn describe_step(frames: &[Frame], step: usize, reveal: bool) -> Vec<String> {
+
+	let Frame{action, game, change, others} = &frames[step];
+	let mut lines: Vec<String> = Vec::new();
+
+	// The header, naming the action which led to this state.
+	lines.push(format!("Step {step}/{}: {action}", frames.len() - 1));
+
+	// The map, either as the player saw it or fully revealed.
+	let map = if reveal {
+		algorithms::visualize_map(&game.map, &game.location, &game.direction, &true)
+	}
+	else {
+		let mut hidden_map = game.map.clone();
+		algorithms::hide_map(&mut hidden_map);
+		algorithms::visualize_map(&hidden_map, &game.location, &game.direction, &false)
+	};
+	lines.extend(map.lines().map(str::to_string));
+	lines.push(String::new());
+
+	// The status, along with how much the action changed the score.
+	let change = match step {
+		0 => String::new(),
+		_ => format!(" ({change:+})"),
+	};
+	lines.push(format!("Position: {} facing {}, arrows: {}, score: {}{change}",
+		game.location,
+		game.direction,
+		game.arrows,
+		game.score,
+	));
+	if ! others.is_empty() {
+		let others: Vec<String> = others
+			.iter()
+			.map(|other| format!("{} at {} with {}{}", other.name, other.location, other.score, if other.active { "" } else { " (out)" }))
+			.collect();
+		lines.push(format!("Others: {}", others.join(", ")));
+	}
+	lines.push(format!("Senses: [{}]", game.events));
+	lines.extend(game.describe_events().iter().map(|message| format!("> {message}")));
+	return lines;
+}
+
+
+// Browses the replay with the keyboard until the player quits.
+fn browse(options: &Options, title: &str, frames: &[Frame]) -> Result<(), String> {
+
+	let mut tui = tui::Tui::new(Duration::ZERO)?;
+	let mut step = options.step;
+	let mut reveal = options.reveal;
+	let last = frames.len() - 1;
+
+	loop {
+		let mut screen = vec![title.to_string()];
+		screen.extend(describe_step(frames, step, reveal));
+		screen.push(String::new());
+		screen.push(format!("Controls: [Left/Right] step, [Up/Down] first/last step, [R] {}, [Q] quit",
+			if reveal { "view of the player" } else { "reveal the map" },
+		));
+		tui.render(&screen);
+
+		match tui.read_key() {
+			Some(Key::Right | Key::Char('d' | ' ')) => step = (step + 1).min(last),
+			Some(Key::Left  | Key::Char('a'))       => step = step.saturating_sub(1),
+			Some(Key::Up    | Key::Char('w'))       => step = 0,
+			Some(Key::Down  | Key::Char('s'))       => step = last,
+			Some(Key::Char('r'))                    => reveal = ! reveal,
+			Some(Key::Char('q')) | None             => return Ok(()),
+			Some(_)                                 => (),
+		}
+	}
+}
+
+
+fn run(options: Options) -> Result<(), String> {
+
+	// Play the game or the match again, making sure it still unfolds as recorded.
+	let (title, frames) = match MatchReplay::load(&options.path) {
+		Ok(replay) => {
+			let players: Vec<String> = replay.players.iter().zip(&replay.models).map(|(player, model)| format!("{player} ({model})")).collect();
+			let mode = if replay.team { "cooperative" } else { "competitive" };
+			(format!("WumpusWorld replay of a {mode} match between {}", players.join(", ")), match_frames(&replay)?)
+		},
+		Err(_) => {
+			let replay = Replay::load(&options.path)?;
+			(format!("WumpusWorld replay of {} playing with the {} model", replay.player, replay.model), game_frames(&replay)?)
+		},
+	};
+	if options.step >= frames.len() {
+		return Err(format!("The replay only has {} steps", frames.len() - 1));
+	}
+
+	if options.print {
+		for step in options.step..frames.len() {
+			println!("{}\n", describe_step(&frames, step, options.reveal).join("\n"));
+		}
+		return Ok(());
+	}
+	return browse(&options, &title, &frames);
+}
+
+
+fn main() {
+
+	let options = match Options::parse(std::env::args().skip(1)) {
+		Ok(Some(options)) => options,
+		Ok(None) => {
+			println!("{USAGE}");
+			return;
+		},
+		Err(message) => {
+			eprintln!("Error: {message}");
+			eprintln!("Run with --help for usage.");
+			std::process::exit(2);
+		},
+	};
+
+	if let Err(message) = run(options) {
+		eprintln!("Error: {message}");
+		std::process::exit(1);
+	}
+}
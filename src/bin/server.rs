@@ -1,69 +1,1848 @@
 
 use std::{
-	net::{TcpListener, TcpStream},
-	sync::{Arc, Mutex},
+	io::{Read, Write},
+	collections::{HashMap, HashSet},
+	net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+	sync::mpsc,
+	time::{Duration, Instant},
 };
 
 use wumpusworld::wumpus;
 use wumpusworld::algorithms;
+use wumpusworld::http;
+use wumpusworld::poll;
+use wumpusworld::websocket;
+use wumpusworld::leaderboard::{self, Entry, Leaderboard};
+use wumpusworld::replay::{MatchReplay, Replay};
+use wumpusworld::multiplayer::{SharedGame, TeamMessage};
+use wumpusworld::protocol::{self, Message, ErrorCode, GameResult, Mode};
 
+use rand::Rng;
 use serde::Deserialize;
 
 
-fn handle_client(stream: TcpStream, high_score: Arc<Mutex<i32>>) {
+const USAGE: &str = "\
+Usage: server [OPTIONS]
 
-	// Show the connected client.
-	let mut de = serde_json::Deserializer::from_reader(&stream);
-	let client_address = stream.peer_addr().expect("Could not determine client address");
-	println!("Client {client_address} connected");
+Hosts WumpusWorld games for clients to connect to.
 
-	// Initialize the game and send the state.
-	let mut game = wumpus::Game::new_random();
-	println!("{}", algorithms::visualize_map(&game.map, &game.location, &game.direction, &true));
+Options:
+  --bind ADDRESS         The address to listen on [default: 127.0.0.1]
+  --port PORT            The port to listen on [default: 6666]
+  --text-port PORT       The port to serve the text protocol on, 0 to disable [default: 6667]
+  --http-port PORT       The port to serve the HTTP API on, 0 to disable [default: 8080]
+  --rules FILE           Load the game rules from a JSON file
+  --maps PATH            Play on the maps of a map file, or of every .txt or .map file in a directory,
+                         which the seed picks from, instead of generating them
+  --leaderboard FILE     Keep the best scores in this JSON file [default: leaderboard.json]
+  --replays DIR          Save the replay of every game in this directory, empty to disable [default: replays]
+  --daily-secret FILE    Keep the secret the daily challenges are derived from in this file, which is created
+                         on the first start, empty to draw a new one every start [default: daily-secret]
+  --max-clients N        The maximum number of concurrent clients [default: 1024]
+  --idle-timeout SECS    Disconnect clients idle for this long, 0 to never [default: 300]
+  --max-strikes N        Disconnect clients after this many bad messages [default: 3]
+  --party-size N         The number of players in a competitive or cooperative match [default: 2]
+  --quiet                Only log the listening addresses and new high scores
+  -h, --help             Print this help text";
 
+const TEXT_HELP: &str = "\
+Commands:
+  walk                   Walk one tile forward
+  left                   Turn to the left
+  right                  Turn to the right
+  dig                    Dig for treasure
+  shoot                  Shoot an arrow forward
+  name NAME              Set the name shown on the leaderboard
+  daily                  Play the daily challenge instead, before the first action
+  help                   Print this help text
+  quit                   Leave the game
+";
+
+// A page to play against the server from a browser, using the WebSocket endpoint.
+const PAGE: &str = include_str!("../../web/index.html");
+
+
+struct Options {
+	bind         : String,
+	port         : u16,
+	text_port    : Option<u16>,
+	http_port    : Option<u16>,
+	rules        : wumpus::Rules,
+	maps         : Vec<(String, wumpus::Map)>,
+	leaderboard  : String,
+	replays      : Option<String>,
+	daily_secret : Option<String>,
+	max_clients  : usize,
+	idle_timeout : Option<Duration>,
+	max_strikes  : u32,
+	party_size   : usize,
+	quiet        : bool,
+}
+
+impl Options {
+
+	// Parses the command line arguments, returning None if help was requested.
+	fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+
+		let mut options = Self {
+			bind         : "127.0.0.1".to_string(),
+			port         : 6666,
+			text_port    : Some(6667),
+			http_port    : Some(8080),
+			rules        : Default::default(),
+			maps         : Vec::new(),
+			leaderboard  : "leaderboard.json".to_string(),
+			replays      : Some("replays".to_string()),
+			daily_secret : Some("daily-secret".to_string()),
+			max_clients  : 1024,
+			idle_timeout : Some(Duration::from_secs(300)),
+			max_strikes  : 3,
+			party_size   : 2,
+			quiet        : false,
+		};
+
+		while let Some(arg) = args.next() {
+
+			let mut value = || args
+				.next()
+				.ok_or(format!("Missing value for {arg}"));
+
+			match arg.as_str() {
+				"--bind"         => options.bind = value()?,
+				"--port"         => options.port = value()?.parse().map_err(|_| "Invalid port")?,
+				"--text-port"    => options.text_port = match value()?.parse().map_err(|_| "Invalid port")? {
+					0 => None,
+					port => Some(port),
+				},
+				"--http-port"    => options.http_port = match value()?.parse().map_err(|_| "Invalid port")? {
+					0 => None,
+					port => Some(port),
+				},
+				"--rules"        => options.rules = wumpus::Rules::load(&value()?)?,
+				"--maps"         => options.maps = wumpus::Map::load_all(&value()?)?,
+				"--leaderboard"  => options.leaderboard = value()?,
+				"--replays"      => options.replays = Some(value()?).filter(|directory| ! directory.is_empty()),
+				"--daily-secret" => options.daily_secret = Some(value()?).filter(|path| ! path.is_empty()),
+				"--max-clients"  => options.max_clients = value()?.parse().map_err(|_| "Invalid client count")?,
+				"--idle-timeout" => options.idle_timeout = match value()?.parse().map_err(|_| "Invalid timeout")? {
+					0 => None,
+					secs => Some(Duration::from_secs(secs)),
+				},
+				"--max-strikes"  => options.max_strikes = value()?.parse().map_err(|_| "Invalid strike count")?,
+				"--party-size"   => options.party_size = value()?.parse().map_err(|_| "Invalid party size")?,
+				"--quiet"        => options.quiet = true,
+				"-h" | "--help"  => return Ok(None),
+				_ => return Err(format!("Unknown option: {arg}")),
+			}
+		}
+
+		if options.party_size == 0 {
+			return Err("A match needs at least one player".to_string());
+		}
+		options.rules.validate_maps(&options.maps)?;
+		return Ok(Some(options));
+	}
+
+}
+
+
+// Who plays a game, recording every action for the leaderboard and the replays.
+struct Player {
+	id     : u64,
+	replay : Replay,
+	daily  : bool, // Whether the game is the daily challenge, ranked apart from other games.
+	ranked : bool, // Whether the game enters the leaderboard, unless a spectator saw the map before it was over.
+}
+
+impl Player {
+
+	fn new(id: u64, name: Option<String>, model: &str, seed: Option<u64>, daily: bool, game: &wumpus::Game) -> Self {
+		let mut replay = Replay::new(game, seed);
+		replay.player = name.unwrap_or_else(|| "anonymous".to_string());
+		replay.model = model.to_string();
+		return Self{id, replay, daily, ranked: true};
+	}
+
+	// Records the game, which only enters the leaderboard if it's over rather than abandoned.
+	fn record(&self, game: &wumpus::Game) -> Record {
+		let replay = &self.replay;
+		let entry = game.game_over.then(|| Entry {
+			player    : replay.player.clone(),
+			model     : replay.model.clone(),
+			score     : game.score,
+			seed      : replay.seed,
+			actions   : replay.steps.len() as u32,
+			timestamp : Entry::now(),
+			rules     : game.rules.clone(),
+			daily     : self.daily,
+			mode      : Mode::Single,
+		});
+		return Record{id: self.id, score: game.score, entry, ranked: self.ranked, replay: replay.clone()};
+	}
+
+}
+
+// A game which ended, to be recorded by the server.
+struct Record {
+	id     : u64,
+	score  : i32,
+	entry  : Option<Entry>, // None if the player left before the game was over.
+	ranked : bool,
+	replay : Replay,
+}
+
+// A game played over the HTTP API, which outlives the connections.
+struct Session {
+	game        : wumpus::Game,
+	player      : Player,
+	last_active : Instant,
+}
+
+// A player waiting in the lobby for a match.
+struct Waiting {
+	id    : u64,
+	name  : String,
+	model : String,
+	mode  : Mode,
+}
+
+// A match between several players sharing the same cave, either competing or as a team.
+struct Match {
+	game    : SharedGame,
+	members : Vec<u64>, // The session id of every player, in the order of the turns.
+	mode    : Mode,
+	replay  : MatchReplay,
+}
+
+impl Match {
+
+	// The game as seen by a player, along with the other players.
+	fn state(&self, index: usize) -> Message {
+		let mut game = self.game.view(index);
+		algorithms::hide_map(&mut game.map);
+		return Message::State{game, others: self.game.others(index)};
+	}
+
+	fn result(&self, index: usize) -> Message {
+		let explorer = &self.game.explorers[index];
+		return Message::GameOver{result: GameResult {
+			score     : explorer.score,
+			won       : self.game.won(index),
+			map       : self.game.map.clone(),
+			replay    : None,
+			standings : self.game.standings(),
+		}};
+	}
+
+	// The leaderboard entries of a match which is over, for every player who didn't leave before the end.
+	fn entries(&self) -> Vec<Entry> {
+		let replay = &self.replay;
+		(0..self.members.len())
+			.filter(|&index| ! replay.left(index))
+			.map(|index| Entry {
+				player    : replay.players[index].clone(),
+				model     : replay.models[index].clone(),
+				score     : self.game.explorers[index].score,
+				seed      : replay.seed,
+				actions   : replay.actions(index),
+				timestamp : Entry::now(),
+				rules     : self.game.rules.clone(),
+				daily     : false,
+				mode      : self.mode,
+			})
+			.collect()
+	}
+
+}
+
+// A file to write in the background, so that slow disks don't hold up the event loop.
+enum Save {
+	Replay(String, Box<Replay>),
+	Match(String, Box<MatchReplay>),
+	Leaderboard(Leaderboard),
+}
+
+// Writes the files it receives in order, until the server stops.
+fn spawn_writer() -> mpsc::Sender<Save> {
+	let (sender, receiver) = mpsc::channel();
+	std::thread::spawn(move || {
+		for save in receiver {
+			let saved = match save {
+				Save::Replay(path, replay) => replay.save(&path),
+				Save::Match(path, replay) => replay.save(&path),
+				Save::Leaderboard(leaderboard) => leaderboard.save(),
+			};
+			if let Err(message) = saved {
+				eprintln!("Error: {message}");
+			}
+		}
+	});
+	return sender;
+}
+
+// The state shared by every connection.
+struct Server {
+	options     : Options,
+	leaderboard : Leaderboard,
+	secret      : leaderboard::Secret, // Which the daily challenges are derived from.
+	writer      : mpsc::Sender<Save>,
+	unsaved     : bool,    // Whether the leaderboard changed since it was last saved.
+	last_saved  : Instant,
+	sessions    : u64,
+	games       : HashMap<u64, Session>,
+	spectated   : HashSet<u64>,              // The sessions watched by spectators.
+	watching    : Vec<(u64, SocketAddr)>,    // Spectators which just started watching a session.
+	broadcasts  : Vec<(u64, Vec<Message>)>,  // The messages for the spectators of every session.
+	lobby       : Vec<Waiting>,
+	caves       : HashMap<Mode, (Option<u64>, wumpus::Game)>, // The cave of the next match of every mode, and its seed.
+	matches     : HashMap<u64, Match>,
+	seats       : HashMap<u64, (u64, usize)>, // The match of every player in one, and their index in it.
+	mail        : Vec<(u64, Vec<Message>)>,   // The messages for the players of matches, by session id.
+}
+
+impl Server {
+
+	fn new(options: Options, leaderboard: Leaderboard, secret: leaderboard::Secret) -> Self {
+		Self {
+			options,
+			leaderboard,
+			secret,
+			writer      : spawn_writer(),
+			unsaved     : false,
+			last_saved  : Instant::now(),
+			sessions    : 0,
+			games       : HashMap::new(),
+			spectated   : HashSet::new(),
+			watching    : Vec::new(),
+			broadcasts  : Vec::new(),
+			lobby       : Vec::new(),
+			caves       : HashMap::new(),
+			matches     : HashMap::new(),
+			seats       : HashMap::new(),
+			mail        : Vec::new(),
+		}
+	}
+
+	fn new_session_id(&mut self) -> u64 {
+		self.sessions += 1;
+		return self.sessions;
+	}
+
+	// Starts a game with the rules of the server, on one of its maps if it has any, which the seed picks.
+	fn new_game(&self, seed: Option<u64>) -> wumpus::Game {
+		let maps = &self.options.maps;
+		if maps.is_empty() {
+			return match seed {
+				Some(seed) => wumpus::Game::new_seeded(&self.options.rules, seed),
+				None => wumpus::Game::new_random(&self.options.rules),
+			};
+		}
+		let index = match seed {
+			Some(seed) => self.map_of(seed) as usize,
+
+			// Games without a seed skip the map of the daily challenge, so it isn't practised by chance.
+			None if maps.len() > 1 => {
+				let daily = self.map_of(self.daily_seed()) as usize;
+				let index = rand::thread_rng().gen_range(0..maps.len() - 1);
+				if index >= daily { index + 1 } else { index }
+			},
+			None => 0,
+		};
+		let (name, map) = &maps[index];
+		self.log(&format!("Playing on the map {name}"));
+		return wumpus::Game::from_map(&self.options.rules.with_map(map), map.clone());
+	}
+
+	// Which map the seed plays: its index among the maps of the server if it has any, or the seed itself.
+	fn map_of(&self, seed: u64) -> u64 {
+		match self.options.maps.len() {
+			0 => seed,
+			count => seed % count as u64,
+		}
+	}
+
+	// The seed of the daily challenge of today.
+	fn daily_seed(&self) -> u64 {
+		leaderboard::daily_seed(&self.secret, leaderboard::today())
+	}
+
+	// The seed to generate a map from, which is that of today if the daily challenge was requested.
+	fn choose_seed(&self, seed: Option<u64>, daily: bool) -> Result<Option<u64>, String> {
+		let today = self.daily_seed();
+		match daily {
+			true => Ok(Some(today)),
+
+			/* Otherwise the map of the daily challenge could be practised unranked, before playing it for the
+			leaderboard. Seeds only pick one of the maps of the server if it has any, so those picking the same
+			map are rejected as well. */
+			false if seed.is_some_and(|seed| self.map_of(seed) == self.map_of(today)) => {
+				Err("The seed picks the map of the daily challenge of today, which is only played as such".to_string())
+			},
+			false => Ok(seed),
+		}
+	}
+
+	// Saves the replay and records the game on the leaderboard if it was over, returning whether it's the new high score of its rules.
+	fn record_game(&mut self, record: Record) -> bool {
+		if let Some(directory) = &self.options.replays {
+			let path = format!("{directory}/{}-{}.json", record.replay.timestamp, record.id);
+			let _ = self.writer.send(Save::Replay(path, Box::new(record.replay)));
+		}
+		let Some(entry) = record.entry else { return false };
+		if ! record.ranked {
+			self.log(&format!("Session {} isn't ranked, it was spectated before the game was over", record.id));
+			return false;
+		}
+		self.unsaved = true;
+		return self.leaderboard.record(entry);
+	}
+
+	// Saves the replay of a match which is over, and records every player who stayed until the end on the leaderboard.
+	fn record_match(&mut self, match_id: u64) {
+		let game = self.matches.remove(&match_id).expect("Only matches in progress end");
+		let standings: Vec<String> = game.game.standings().iter().map(|player| format!("{} ({})", player.name, player.score)).collect();
+		self.log(&format!("Match {match_id} is over: {}", standings.join(", ")));
+
+		for entry in game.entries() {
+			self.unsaved = true;
+			if self.leaderboard.record(entry.clone()) {
+				self.log(&format!("Match {match_id} ended with a new high score of {} by {}!", entry.score, entry.player));
+			}
+		}
+		if let Some(directory) = &self.options.replays {
+			let path = format!("{directory}/{}-match-{match_id}.json", game.replay.timestamp);
+			let _ = self.writer.send(Save::Match(path, Box::new(game.replay)));
+		}
+	}
+
+	// Saves the leaderboard in the background if it changed, at most once per interval to batch the games.
+	fn save_leaderboard(&mut self) {
+		const INTERVAL: Duration = Duration::from_secs(1);
+
+		if self.unsaved && self.last_saved.elapsed() >= INTERVAL {
+			let _ = self.writer.send(Save::Leaderboard(self.leaderboard.clone()));
+			self.unsaved = false;
+			self.last_saved = Instant::now();
+		}
+	}
+
+	// Shows the spectators of the session the game after the last action.
+	fn broadcast(&mut self, game: &wumpus::Game, player: &Player) {
+		if self.spectated.contains(&player.id) {
+			self.broadcasts.push((player.id, reveal_state(game, player)));
+		}
+	}
+
+	// The rules of the games of the server, which are derived from its maps if it has any.
+	fn rules_played(&self) -> Vec<wumpus::Rules> {
+		if self.options.maps.is_empty() {
+			return vec![self.options.rules.clone()];
+		}
+		let mut rules: Vec<wumpus::Rules> = Vec::new();
+		for (_, map) in &self.options.maps {
+			let map_rules = self.options.rules.with_map(map);
+			if ! rules.contains(&map_rules) {
+				rules.push(map_rules);
+			}
+		}
+		return rules;
+	}
+
+	// The best entries of the daily challenge of today, without the seed, which is kept secret until the day ends.
+	fn daily_entries(&self, rules: &[wumpus::Rules], count: usize) -> Vec<Entry> {
+		let seed = self.daily_seed();
+		let mut entries = self.leaderboard.daily(rules, seed, count);
+		entries.iter_mut().for_each(|entry| entry.seed = None);
+		return entries;
+	}
+
+	// Tells the players waiting for a match of the mode who else is waiting.
+	fn mail_lobby(&mut self, mode: Mode) {
+		let waiting: Vec<&Waiting> = self.lobby.iter().filter(|waiting| waiting.mode == mode).collect();
+		let players: Vec<String> = waiting.iter().map(|waiting| waiting.name.clone()).collect();
+		for waiting in waiting {
+			self.mail.push((waiting.id, vec![Message::Lobby{players: players.clone(), needed: self.options.party_size}]));
+		}
+	}
+
+
+	/* Adds a player to the lobby, starting a match once there are enough players of the same mode, and
+	returns the rules of the match. The cave is picked as soon as the first player joins, from their seed if
+	they gave one, so that every player is welcomed with the rules they'll play by. */
+	fn join_lobby(&mut self, waiting: Waiting, seed: Option<u64>) -> wumpus::Rules {
+
+		let mode = waiting.mode;
+		if ! self.caves.contains_key(&mode) {
+			let game = self.new_game(seed);
+			self.caves.insert(mode, (seed, game));
+		}
+		let rules = self.caves[&mode].1.rules.clone();
+		self.lobby.push(waiting);
+		self.mail_lobby(mode);
+		if self.lobby.iter().filter(|waiting| waiting.mode == mode).count() < self.options.party_size {
+			return rules;
+		}
+
+		let (lobby, rest): (Vec<Waiting>, Vec<Waiting>) = std::mem::take(&mut self.lobby)
+			.into_iter()
+			.partition(|waiting| waiting.mode == mode);
+		self.lobby = rest;
+		let (seed, game) = self.caves.remove(&mode).expect("The cave is picked by the first player");
+		let players: Vec<String> = lobby.iter().map(|waiting| waiting.name.clone()).collect();
+		let models: Vec<String> = lobby.iter().map(|waiting| waiting.model.clone()).collect();
+		let match_id = self.new_session_id();
+		self.log(&format!("{mode:?} match {match_id} started with {}", players.join(", ")));
+		self.log(&algorithms::visualize_map(&game.map, &game.location, &game.direction, &true));
+
+		let members: Vec<u64> = lobby.iter().map(|waiting| waiting.id).collect();
+		for (index, member) in members.iter().enumerate() {
+			self.seats.insert(*member, (match_id, index));
+		}
+		let team = mode == Mode::Cooperative;
+		let replay = MatchReplay::new(&game, seed, players.clone(), models, team);
+		let game = Match{game: SharedGame::new(&game, players, team), members, mode, replay};
+		self.mail.push((game.members[game.game.turn], vec![game.state(game.game.turn)]));
+		self.matches.insert(match_id, game);
+		return rules;
+	}
+
+
+	// Performs the action of a player in a match, if it's their turn.
+	fn play_turn(&mut self, id: u64, action: wumpus::Action) -> Result<(), String> {
+
+		let Some(&(match_id, index)) = self.seats.get(&id) else {
+			return Err("The match hasn't started yet".to_string());
+		};
+		let game = self.matches.get_mut(&match_id).expect("Seats belong to a match");
+		if game.game.turn != index {
+			return Err(format!("It's the turn of {}", game.game.explorers[game.game.turn].name));
+		}
+		let hit = game.replay.act(&mut game.game, action);
+
+		// Players out of the game see how it ended, either everyone or those who were eliminated.
+		let finished: Vec<usize> = match game.game.game_over {
+			true => (0..game.members.len()).collect(),
+			false => hit.into_iter().chain((! game.game.explorers[index].active).then_some(index)).collect(),
+		};
+		for other in finished {
+			let member = game.members[other];
+			if self.seats.remove(&member).is_some() {
+				self.mail.push((member, vec![game.state(other), game.result(other)]));
+			}
+		}
+
+		if game.game.game_over {
+			self.record_match(match_id);
+		}
+		else {
+			self.mail.push((game.members[game.game.turn], vec![game.state(game.game.turn)]));
+		}
+		return Ok(());
+	}
+
+
+	// Relays a message from a player to the others in their match.
+	fn chat(&mut self, id: u64, mut message: TeamMessage) -> Result<(), String> {
+		let Some(&(match_id, index)) = self.seats.get(&id) else {
+			return Err("The match hasn't started yet".to_string());
+		};
+		let game = &self.matches[&match_id];
+		message.from = game.game.explorers[index].name.clone();
+		self.log(&format!("- {} says: {}", message.from, message.text));
+		for &member in &game.members {
+			if member != id && self.seats.contains_key(&member) {
+				self.mail.push((member, vec![Message::Chat{message: message.clone()}]));
+			}
+		}
+		return Ok(());
+	}
+
+
+	// The state to send again to a player of a match, if it's their turn.
+	fn turn_state(&self, id: u64) -> Option<Message> {
+		let (match_id, index) = self.seats.get(&id)?;
+		let game = self.matches.get(match_id)?;
+		return (game.game.turn == *index).then(|| game.state(*index));
+	}
+
+
+	// Removes a player who left from the lobby or their match.
+	fn leave(&mut self, id: u64) {
+
+		if let Some(position) = self.lobby.iter().position(|waiting| waiting.id == id) {
+			let waiting = self.lobby.remove(position);
+			self.mail_lobby(waiting.mode);
+			if ! self.lobby.iter().any(|other| other.mode == waiting.mode) {
+				self.caves.remove(&waiting.mode);
+			}
+			return;
+		}
+
+		let Some((match_id, index)) = self.seats.remove(&id) else { return };
+		self.log(&format!("- {} left match {match_id}", self.matches[&match_id].game.explorers[index].name));
+		let game = self.matches.get_mut(&match_id).expect("Seats belong to a match");
+		let turn = game.game.turn;
+		game.replay.leave(&mut game.game, index);
+		if game.game.game_over {
+			self.record_match(match_id);
+		}
+		else if turn == index {
+			self.mail.push((game.members[game.game.turn], vec![game.state(game.game.turn)]));
+		}
+	}
+
+
+	// Logs the progress of a client, unless the server is quiet.
+	fn log(&self, message: &str) {
+		if ! self.options.quiet {
+			println!("{message}");
+		}
+	}
+
+}
+
+// --- JSON protocol
+
+// The outcome of waiting for a message from a client.
+#[allow(clippy::large_enum_variant)] // Only ever held briefly on the stack.
+enum Received {
+	Message(Message),
+	Invalid(String),   // The message is valid JSON, but not a known message.
+	Malformed(String), // The message isn't valid JSON, and was skipped up to where the next one may start.
+	TimedOut,
+	Closed(String),
+}
+
+// Parses the next message from the start of the input, or returns None if it hasn't been received entirely yet.
+fn parse_message(input: &mut Vec<u8>) -> Option<Received> {
+
+	// Parse the JSON first, so messages which aren't understood can be skipped.
+	let mut values = serde_json::Deserializer::from_slice(input).into_iter::<serde_json::Value>();
+	let value = values.next();
+	let length = values.byte_offset();
+	let received = match value {
+		None => None,
+		Some(Err(e)) if e.is_eof() => return None,
+		Some(Err(e)) => {
+			// Resynchronise on the next line or object, skipping at least the first byte of the bad message.
+			let start = input[length..]
+				.iter()
+				.position(|byte| ! byte.is_ascii_whitespace())
+				.map_or(input.len(), |index| length + index + 1);
+			let next = input[start..]
+				.iter()
+				.position(|&byte| byte == b'\n' || byte == b'{')
+				.map_or(input.len(), |index| start + index);
+			input.drain(..next);
+			return Some(Received::Malformed(e.to_string()));
+		},
+		Some(Ok(value)) => Some(match Message::deserialize(value) {
+			Ok(message) => Received::Message(message),
+			Err(e) => Received::Invalid(e.to_string()),
+		}),
+	};
+	input.drain(..length);
+	return received;
+}
+
+// A game played over the JSON protocol, either as a raw stream or over a WebSocket.
+struct JsonSession {
+	game       : Option<(wumpus::Game, Player)>, // None until the client has greeted.
+	spectating : Option<u64>,                    // The session watched instead of playing.
+	member     : Option<u64>,                    // The session id of the player in a match, instead of playing alone.
+	strikes    : u32,
+}
+
+impl JsonSession {
+
+	fn new() -> Self {
+		Self {
+			game       : None,
+			spectating : None,
+			member     : None,
+			strikes    : 0,
+		}
+	}
+
+	// Handles what was received from the client, queueing the replies. Returns false once the session is over.
+	fn handle(&mut self, received: Received, client_address: SocketAddr, server: &mut Server, replies: &mut Vec<Message>) -> bool {
+
+		// The leaderboard can be queried at any time, even without playing.
+		if let Received::Message(Message::LeaderboardQuery{count, rules, daily, mode}) = &received {
+			let rules = match rules {
+				Some(rules) => vec![rules.clone()],
+				None => server.rules_played(),
+			};
+			let entries = match daily {
+				true => server.daily_entries(&rules, *count),
+				false => server.leaderboard.top(&rules, *mode, *count),
+			};
+			replies.push(Message::Leaderboard{entries});
+			return true;
+		}
+
+		// Wait for the client to greet, and make sure it speaks the same protocol.
+		if self.game.is_none() && self.spectating.is_none() && self.member.is_none() {
+			match received {
+				Received::Message(Message::Hello{version, mode, daily: true, ..}) if version == protocol::VERSION && mode != Mode::Single => {
+					server.log(&format!("Client {client_address} rejected, it asked for the daily challenge in a {mode:?} match"));
+					replies.push(Message::error(ErrorCode::InvalidMessage, "The daily challenge is only played alone"));
+				},
+				Received::Message(Message::Hello{version, client_name, player, model, seed, mode, ..}) if version == protocol::VERSION && mode != Mode::Single => {
+					let seed = match server.choose_seed(seed, false) {
+						Ok(seed) => seed,
+						Err(message) => {
+							server.log(&format!("Client {client_address} rejected, it asked for the seed of the daily challenge"));
+							replies.push(Message::error(ErrorCode::InvalidMessage, &message));
+							return false;
+						},
+					};
+					let id = server.new_session_id();
+					let name = player.unwrap_or_else(|| format!("player {id}"));
+					server.log(&format!("Client {client_address} is {client_name} waiting for a {mode:?} match as {name} with the {model} model"));
+					let rules = server.join_lobby(Waiting{id, name, model, mode}, seed);
+					replies.push(Message::Welcome{session_id: id, rules});
+					self.member = Some(id);
+					return true;
+				},
+				Received::Message(Message::Hello{version, client_name, player, model, seed, daily, ..}) if version == protocol::VERSION => {
+
+					// Initialize the game and welcome the client.
+					let seed = match server.choose_seed(seed, daily) {
+						Ok(seed) => seed,
+						Err(message) => {
+							server.log(&format!("Client {client_address} rejected, it asked for the seed of the daily challenge"));
+							replies.push(Message::error(ErrorCode::InvalidMessage, &message));
+							return false;
+						},
+					};
+					let game = server.new_game(seed);
+					let player = Player::new(server.new_session_id(), player, &model, seed, daily, &game);
+					server.log(&format!("Client {client_address} is {client_name} playing {} as {} with the {model} model",
+						if daily { "the daily challenge" } else { "a game" },
+						player.replay.player,
+					));
+					server.log(&algorithms::visualize_map(&game.map, &game.location, &game.direction, &true));
+					replies.push(Message::Welcome{session_id: player.id, rules: game.rules.clone()});
+					let (game, player) = self.game.insert((game, player));
+					return send_state(game, &player.replay, replies);
+				},
+				Received::Message(Message::Spectate{version, session_id}) if version == protocol::VERSION => {
+					server.log(&format!("Client {client_address} spectates session {session_id}"));
+					server.watching.push((session_id, client_address));
+					self.spectating = Some(session_id);
+					return true;
+				},
+				Received::Message(Message::Hello{version, ..} | Message::Spectate{version, ..}) => {
+					server.log(&format!("Client {client_address} rejected, it speaks protocol version {version}"));
+					replies.push(Message::error(ErrorCode::VersionMismatch, &format!(
+						"The server speaks protocol version {}, but the client speaks version {version}",
+						protocol::VERSION,
+					)));
+				},
+				Received::Message(_) | Received::Invalid(_) | Received::Malformed(_) => {
+					server.log(&format!("Client {client_address} rejected, it didn't greet"));
+					replies.push(Message::error(ErrorCode::UnexpectedMessage, "Expected a Hello message"));
+				},
+				Received::TimedOut | Received::Closed(_) => {
+					server.log(&format!("Client {client_address} disconnected without greeting"));
+				},
+			}
+			return false;
+		}
+
+		// Perform the action from the client.
+		let (code, reason) = match received {
+			Received::Message(Message::Action{action}) => match (&mut self.game, self.member) {
+				(Some((game, player)), _) => {
+					server.log(&format!("- {client_address} performs: {action}"));
+					player.replay.act(game, action);
+					server.broadcast(game, player);
+					return send_state(game, &player.replay, replies);
+				},
+				(None, Some(id)) => match server.play_turn(id, action) {
+					Ok(()) => {
+						server.log(&format!("- {client_address} performs: {action}"));
+						return true;
+					},
+					Err(reason) => (ErrorCode::UnexpectedMessage, reason),
+				},
+				(None, None) => (ErrorCode::UnexpectedMessage, "Spectators can't perform actions".to_string()),
+			},
+			Received::Message(Message::Chat{message}) => match self.member {
+				Some(id) => match server.chat(id, message) {
+					Ok(()) => return true,
+					Err(reason) => (ErrorCode::UnexpectedMessage, reason),
+				},
+				None => (ErrorCode::UnexpectedMessage, "Chat is only available in matches".to_string()),
+			},
+
+			Received::Message(_) if self.spectating.is_some() => (ErrorCode::UnexpectedMessage, "Spectators can only query the leaderboard".to_string()),
+			Received::Message(_) => (ErrorCode::UnexpectedMessage, "Expected an Action message".to_string()),
+			Received::Invalid(e) => (ErrorCode::InvalidMessage, format!("Unknown message: {e}")),
+			Received::Malformed(e) => (ErrorCode::MalformedMessage, format!("Unable to parse the message: {e}")),
+
+			// Stop once the connection is closed or the client has been idle for too long.
+			Received::TimedOut => {
+				server.log(&format!("- {client_address} timed out after being idle"));
+				replies.push(Message::error(ErrorCode::Timeout, "Disconnected after being idle for too long"));
+				return false;
+			},
+			Received::Closed(e) => {
+				server.log(&format!("- {client_address} stopped responding: {e}"));
+				return false;
+			},
+		};
+
+		// Tell the client what went wrong, and disconnect it once it has made too many mistakes.
+		self.strikes += 1;
+		server.log(&format!("- {client_address} sent a bad message ({}/{}): {reason}", self.strikes, server.options.max_strikes));
+		if self.strikes >= server.options.max_strikes {
+			server.log(&format!("- {client_address} disconnected after too many bad messages"));
+			replies.push(Message::error(ErrorCode::TooManyErrors, &format!("Disconnected after {} bad messages, the last one being: {reason}", self.strikes)));
+			return false;
+		}
+		replies.push(Message::error(code, &reason));
+		match (&self.game, self.member) {
+			(Some((game, player)), _) => return send_state(game, &player.replay, replies),
+			(None, Some(id)) => replies.extend(server.turn_state(id)),
+			(None, None) => (),
+		}
+		return true;
+	}
+
+	fn record(&self) -> Option<Record> {
+		self.game.as_ref().map(|(game, player)| player.record(game))
+	}
+
+}
+
+// Queues the game state, followed by the result once the game is over. Returns false once the game is over.
+fn send_state(game: &wumpus::Game, replay: &Replay, replies: &mut Vec<Message>) -> bool {
+
+	// Hide undiscovered information from the client.
+	let mut hidden_game = game.clone();
+	algorithms::hide_map(&mut hidden_game.map);
+	replies.push(Message::State{game: hidden_game, others: Vec::new()});
+
+	// Reveal the map once the game is over.
+	if game.game_over {
+		replies.push(Message::GameOver{result: GameResult::new(game, replay)});
+		return false;
+	}
+	return true;
+}
+
+// The messages for spectators, who see the game fully revealed. The daily challenge is only shown once
+// it's over, so that spectators can't learn the map of today before playing it.
+fn reveal_state(game: &wumpus::Game, player: &Player) -> Vec<Message> {
+	if player.daily && ! game.game_over {
+		return Vec::new();
+	}
+	let mut messages = vec![Message::State{game: game.clone(), others: Vec::new()}];
+	if game.game_over {
+		messages.push(Message::GameOver{result: GameResult::new(game, &player.replay)});
+	}
+	return messages;
+}
+
+// The messages for a new spectator. The map could be passed on to the player, e.g. by spectating their
+// own game, so games which are shown before they're over no longer enter the leaderboard.
+fn spectate(game: &wumpus::Game, player: &mut Player) -> Vec<Message> {
+	let messages = reveal_state(game, player);
+	if ! game.game_over && ! messages.is_empty() {
+		player.ranked = false;
+	}
+	return messages;
+}
+
+// --- Text protocol
+
+// A game played by typing commands, e.g. through telnet or netcat.
+struct TextSession {
+	game    : wumpus::Game,
+	player  : Player,
+	strikes : u32,
+}
+
+impl TextSession {
+
+	// Starts a game, returning the session along with the welcome text.
+	fn new(server: &mut Server) -> (Self, String) {
+		let game = server.new_game(None);
+		let session = Self {
+			player  : Player::new(server.new_session_id(), None, "manual", None, false, &game),
+			game,
+			strikes : 0,
+		};
+		server.log(&algorithms::visualize_map(&session.game.map, &session.game.location, &session.game.direction, &true));
+		let welcome = format!("Welcome to WumpusWorld, player {}! Find all the treasures without getting eaten.\n{TEXT_HELP}{}",
+			session.player.id,
+			session.board(),
+		);
+		return (session, welcome);
+	}
+
+	// Shows the board and the events, revealing the map once the game is over.
+	fn board(&self) -> String {
+		let game = &self.game;
+		let mut text = if game.game_over {
+			algorithms::visualize_map(&game.map, &game.location, &game.direction, &true)
+		}
+		else {
+			let mut hidden_map = game.map.clone();
+			algorithms::hide_map(&mut hidden_map);
+			algorithms::visualize_map(&hidden_map, &game.location, &game.direction, &false)
+		};
+		game.describe_events().iter().for_each(|message| text.push_str(&format!("> {message}\n")));
+		if game.game_over {
+			text.push_str(&format!("Final score: {}\n", game.score));
+		}
+		else {
+			text.push_str(&format!("Position: {} facing {}, arrows: {}, score: {}\nAction: ",
+				game.location,
+				game.direction,
+				game.arrows,
+				game.score,
+			));
+		}
+		return text;
+	}
+
+	// Performs a command from the player, returning the reply and whether the session goes on.
+	fn handle_line(&mut self, line: &str, client_address: SocketAddr, server: &mut Server) -> (String, bool) {
+
+		let command = line.trim().to_lowercase();
+		match command.as_str() {
+			"" => return (self.board(), true),
+			"help" => return (format!("{TEXT_HELP}{}", self.board()), true),
+			"quit" => {
+				server.log(&format!("- {client_address} quit"));
+				return ("Goodbye!\n".to_string(), false);
+			},
+			"name" => return (format!("Usage: name NAME\n{}", self.board()), true),
+			_ if command.starts_with("name ") => {
+				// Keep the case of the name, which the command was lowered from.
+				let name = line.trim().get(5..).unwrap_or_default().trim();
+				server.log(&format!("- {client_address} is named {name}"));
+				self.player.replay.player = name.to_string();
+				return (format!("Your name is now {name}.\n{}", self.board()), true);
+			},
+			"daily" if self.player.replay.steps.is_empty() => {
+				// Start over with the map of today, keeping the session and the name.
+				let seed = Some(server.daily_seed());
+				self.game = server.new_game(seed);
+				let name = std::mem::take(&mut self.player.replay.player);
+				self.player = Player::new(self.player.id, Some(name), "manual", seed, true, &self.game);
+				server.log(&format!("- {client_address} plays the daily challenge"));
+				return (format!("Playing the daily challenge of {}.\n{}", leaderboard::format_day(leaderboard::today()), self.board()), true);
+			},
+			"daily" => return (format!("The daily challenge can only be started before the first action.\n{}", self.board()), true),
+			_ => if let Ok(action) = command.parse::<wumpus::Action>() {
+				server.log(&format!("- {client_address} performs: {action}"));
+				self.player.replay.act(&mut self.game, action);
+				server.broadcast(&self.game, &self.player);
+				return (self.board(), ! self.game.game_over);
+			},
+		}
+
+		// Tell the player about the unknown command, and disconnect it once it has made too many mistakes.
+		self.strikes += 1;
+		server.log(&format!("- {client_address} sent an unknown command ({}/{}): {command}", self.strikes, server.options.max_strikes));
+		if self.strikes >= server.options.max_strikes {
+			server.log(&format!("- {client_address} disconnected after too many unknown commands"));
+			return (format!("Disconnected after {} unknown commands.\n", self.strikes), false);
+		}
+		return (format!("Unknown command: {command}. Type help for the list of commands.\n{}", self.board()), true);
+	}
+
+}
+
+// --- HTTP API
+
+fn route(request: &http::Request, server: &mut Server) -> http::Response {
+	match (request.method.as_str(), request.segments().as_slice()) {
+		("GET",  []) => http::Response::new(200, "text/html; charset=utf-8", PAGE),
+		("POST", ["games"]) => create_game(request, server),
+		("GET",  ["games", id]) => match find_game(&mut server.games, id) {
+			Some((_, session)) => game_response(200, session),
+			None => http::Response::error(404, "No such game"),
+		},
+		("POST", ["games", id, "actions"]) => {
+			let action = match serde_json::from_slice::<protocol::ActionRequest>(&request.body) {
+				Ok(body) => body.action,
+				Err(e) => return http::Response::error(400, &format!("Invalid action: {e}")),
+			};
+			let Some((id, session)) = find_game(&mut server.games, id) else {
+				return http::Response::error(404, "No such game");
+			};
+			if session.game.game_over {
+				return http::Response::error(409, "The game is already over");
+			}
+			session.player.replay.act(&mut session.game, action);
+			if server.spectated.contains(&id) {
+				server.broadcasts.push((id, reveal_state(&session.game, &session.player)));
+			}
+			let response = game_response(200, session);
+			if session.game.game_over {
+				let record = session.player.record(&session.game);
+				let (score, player) = (record.score, record.replay.player.clone());
+				if server.record_game(record) {
+					println!("HTTP game {id} ended with a new high score of {score} by {player}!");
+				}
+			}
+			response
+		},
+		("GET", ["highscores"]) => {
+			let count = match request.query("count").map(str::parse) {
+				None => 10,
+				Some(Ok(count)) => count,
+				Some(Err(_)) => return http::Response::error(400, "Invalid count"),
+			};
+			let mode = match request.query("mode").map(str::parse) {
+				None => Mode::Single,
+				Some(Ok(mode)) => mode,
+				Some(Err(message)) => return http::Response::error(400, &message),
+			};
+			let entries = server.leaderboard.top(&server.rules_played(), mode, count);
+			let high_score = entries.first().map(|entry| entry.score);
+			http::Response::json(200, &serde_json::json!({"high_score": high_score, "entries": entries}))
+		},
+		("GET", ["daily"]) => {
+			let count = match request.query("count").map(str::parse) {
+				None => 10,
+				Some(Ok(count)) => count,
+				Some(Err(_)) => return http::Response::error(400, "Invalid count"),
+			};
+			let entries = server.daily_entries(&server.rules_played(), count);
+			http::Response::json(200, &serde_json::json!({"date": leaderboard::format_day(leaderboard::today()), "entries": entries}))
+		},
+		(_, [] | ["games"] | ["games", _] | ["games", _, "actions"] | ["highscores"] | ["daily"]) => http::Response::error(405, "Method not allowed"),
+		_ => http::Response::error(404, "Not found"),
+	}
+}
+
+
+fn create_game(request: &http::Request, server: &mut Server) -> http::Response {
+
+	// An empty body starts a random game with the rules of the server.
+	let body: protocol::NewGameRequest = match request.body.is_empty() {
+		true => Default::default(),
+		false => match serde_json::from_slice(&request.body) {
+			Ok(body) => body,
+			Err(e) => return http::Response::error(400, &format!("Invalid game request: {e}")),
+		},
+	};
+	if let Some(Err(message)) = body.rules.as_ref().map(wumpus::Rules::validate) {
+		return http::Response::error(400, &message);
+	}
+
+	// Forget games which haven't been played for a while, and make room for the new one.
+	if let Some(timeout) = server.options.idle_timeout {
+		server.games.retain(|_, session| session.last_active.elapsed() < timeout);
+	}
+	if server.games.len() >= server.options.max_clients {
+		return http::Response::error(503, "The server is full, try again later");
+	}
+
+	let seed = match server.choose_seed(body.seed, body.daily) {
+		Ok(seed) => seed,
+		Err(message) => return http::Response::error(400, &message),
+	};
+	let game = match (&body.rules, seed) {
+		(None, seed) => server.new_game(seed),
+		(Some(rules), Some(seed)) => wumpus::Game::new_seeded(rules, seed),
+		(Some(rules), None) => wumpus::Game::new_random(rules),
+	};
+	let player = Player::new(server.new_session_id(), body.player, body.model.as_deref().unwrap_or("manual"), seed, body.daily, &game);
+	let session = Session{game, player, last_active: Instant::now()};
+	let response = game_response(201, &session);
+	server.games.insert(session.player.id, session);
+	return response;
+}
+
+
+fn find_game<'a>(games: &'a mut HashMap<u64, Session>, id: &str) -> Option<(u64, &'a mut Session)> {
+	let id: u64 = id.parse().ok()?;
+	let session = games.get_mut(&id)?;
+	session.last_active = Instant::now();
+	return Some((id, session));
+}
+
+
+fn game_response(status: u16, session: &Session) -> http::Response {
+	let game = &session.game;
+	let mut hidden_game = game.clone();
+	algorithms::hide_map(&mut hidden_game.map);
+	return http::Response::json(status, &protocol::GameResponse {
+		id     : session.player.id,
+		game   : hidden_game,
+		result : game.game_over.then(|| GameResult::new(game, &session.player.replay)),
+	});
+}
+
+// --- Connections
+
+#[derive(Clone, Copy)]
+enum Protocol {
+	Json,
+	Text,
+	Http,
+}
+
+enum State {
+	Json(JsonSession),
+	Text(TextSession),
+	Http,
+	WebSocket(JsonSession, websocket::Decoder),
+	Closing(Instant), // Waiting for the client to hang up, since the session is over.
+	Closed,
+}
+
+struct Connection {
+	stream      : TcpStream,
+	address     : SocketAddr,
+	state       : State,
+	input       : Vec<u8>,
+	output      : Vec<u8>,
+	last_active : Instant,
+	finished    : bool, // Close the connection once the output has been sent.
+}
+
+impl Connection {
+
+	const READ_CHUNK : usize    = 1 << 14;
+	const MAX_INPUT  : usize    = 1 << 16; // Stop reading from clients sending more than can be handled.
+	const MAX_OUTPUT : usize    = 1 << 20; // Stop reading from clients which don't read their replies.
+	const LINGER     : Duration = Duration::from_secs(1);
+
+	fn new(stream: TcpStream, address: SocketAddr, protocol: Protocol, server: &mut Server) -> Self {
+		let mut connection = Self {
+			stream,
+			address,
+			state       : State::Http,
+			input       : Vec::new(),
+			output      : Vec::new(),
+			last_active : Instant::now(),
+			finished    : false,
+		};
+		match protocol {
+			Protocol::Json => {
+				server.log(&format!("Client {address} connected"));
+				connection.state = State::Json(JsonSession::new());
+			},
+			Protocol::Text => {
+				server.log(&format!("Client {address} connected using the text protocol"));
+				let (session, welcome) = TextSession::new(server);
+				connection.send_text(&welcome);
+				connection.state = State::Text(session);
+			},
+			Protocol::Http => (),
+		}
+		return connection;
+	}
+
+
+	// The events to wait for, applying backpressure to clients which don't keep up.
+	fn interest(&self) -> i16 {
+		let mut events = 0;
+		if self.output.len() < Self::MAX_OUTPUT && self.input.len() < Self::MAX_INPUT {
+			events |= poll::READABLE;
+		}
+		if ! self.output.is_empty() {
+			events |= poll::WRITABLE;
+		}
+		return events;
+	}
+
+
+	fn send_messages(&mut self, messages: &[Message], websocket: bool) {
+		for message in messages {
+			let text = serde_json::to_string(message).expect("Unable to serialize the message");
+			match websocket {
+				true => self.output.extend(websocket::encode_frame(websocket::OPCODE_TEXT, text.as_bytes(), false)),
+				false => self.output.extend(text.as_bytes()),
+			}
+		}
+	}
+
+	fn send_text(&mut self, text: &str) {
+		self.output.extend(text.replace('\n', "\r\n").as_bytes()); // Telnet expects CRLF line endings.
+	}
+
+
+	// Reads whatever the client has sent, and handles it.
+	fn receive(&mut self, server: &mut Server) {
+
+		let mut closed: Option<String> = None;
+		let mut chunk = [0u8; Self::READ_CHUNK];
+		while self.input.len() < Self::MAX_INPUT {
+			match self.stream.read(&mut chunk) {
+				Ok(0) => {
+					closed = Some("The connection was closed".to_string());
+					break;
+				},
+				Ok(length) => {
+					self.input.extend_from_slice(&chunk[..length]);
+					self.last_active = Instant::now();
+				},
+				Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+				Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+				Err(e) => {
+					closed = Some(e.to_string());
+					break;
+				},
+			}
+		}
+
+		self.process(server);
+		if let Some(reason) = closed {
+			self.disconnect(&reason, server);
+		}
+	}
+
+
+	// Handles the complete messages in the input.
+	fn process(&mut self, server: &mut Server) {
+		while ! self.finished {
+			let progressed = match std::mem::replace(&mut self.state, State::Closed) {
+
+				State::Json(mut session) => {
+					let received = parse_message(&mut self.input);
+					let progressed = received.is_some();
+					if let Some(received) = received {
+						let mut replies = Vec::new();
+						let open = session.handle(received, self.address, server, &mut replies);
+						self.send_messages(&replies, false);
+						if ! open { self.finish(session.record(), server); }
+					}
+					self.state = State::Json(session);
+					progressed
+				},
+
+				State::Text(mut session) => match self.input.iter().position(|&byte| byte == b'\n') {
+					Some(index) => {
+						let line: Vec<u8> = self.input.drain(..=index).collect();
+						let (reply, open) = session.handle_line(&String::from_utf8_lossy(&line), self.address, server);
+						self.send_text(&reply);
+						if ! open { self.finish(Some(session.player.record(&session.game)), server); }
+						self.state = State::Text(session);
+						true
+					},
+					None => {
+						self.state = State::Text(session);
+						false
+					},
+				},
+
+				State::Http => match http::Request::parse(&self.input) {
+					Ok(Some((request, length))) => {
+						// Stay in the HTTP state while the response is sent, unless the request upgraded the connection.
+						self.input.drain(..length);
+						self.state = State::Http;
+						self.handle_request(&request, server);
+						true
+					},
+					Ok(None) => {
+						self.state = State::Http;
+						false
+					},
+					Err(message) => {
+						server.log(&format!("HTTP {} sent a bad request: {message}", self.address));
+						self.output.extend(http::Response::error(400, &message).to_bytes());
+						self.state = State::Http;
+						self.finish(None, server);
+						false
+					},
+				},
+
+				State::WebSocket(mut session, mut decoder) => {
+					decoder.push(&self.input);
+					self.input.clear();
+					let mut replies = Vec::new();
+					let open = match decoder.decode() {
+
+						// Every message arrives in its own frame, so even invalid JSON can be recovered from.
+						Ok(Some(websocket::Incoming::Text(text))) => session.handle(match serde_json::from_str(&text) {
+							Ok(message) => Received::Message(message),
+							Err(e) => Received::Invalid(e.to_string()),
+						}, self.address, server, &mut replies),
+
+						Ok(Some(websocket::Incoming::Ping(payload))) => {
+							self.output.extend(websocket::encode_frame(websocket::OPCODE_PONG, &payload, false));
+							true
+						},
+						Ok(Some(websocket::Incoming::Close(_))) => session.handle(Received::Closed("The WebSocket was closed".to_string()), self.address, server, &mut replies),
+						Ok(None) => {
+							self.state = State::WebSocket(session, decoder);
+							break;
+						},
+						Err(message) => session.handle(Received::Closed(message), self.address, server, &mut replies),
+					};
+					self.send_messages(&replies, true);
+					if ! open {
+						self.output.extend(websocket::encode_frame(websocket::OPCODE_CLOSE, &[], false));
+						self.finish(session.record(), server);
+					}
+					self.state = State::WebSocket(session, decoder);
+					true
+				},
+
+				state => {
+					self.input.clear();
+					self.state = state;
+					false
+				},
+			};
+			if ! progressed {
+				break;
+			}
+		}
+	}
+
+
+	fn handle_request(&mut self, request: &http::Request, server: &mut Server) {
+
+		// Upgrade to a WebSocket, carrying the JSON protocol.
+		if request.segments() == ["ws"] {
+			match websocket::accept(request) {
+				Ok(response) => {
+					server.log(&format!("Client {} connected using a WebSocket", self.address));
+					self.output.extend(response.to_bytes());
+					self.state = State::WebSocket(JsonSession::new(), websocket::Decoder::default());
+				},
+				Err(message) => {
+					self.output.extend(http::Response::error(400, &message).to_bytes());
+					self.finish(None, server);
+				},
+			}
+			return;
+		}
+
+		// Serve a single request per connection.
+		let response = route(request, server);
+		server.log(&format!("HTTP {} {} {} -> {}", self.address, request.method, request.path, response.status));
+		self.output.extend(response.to_bytes());
+		self.finish(None, server);
+	}
+
+
+	// Ends the session, recording the final score if a game was played.
+	fn finish(&mut self, record: Option<Record>, server: &mut Server) {
+		if self.finished {
+			return;
+		}
+		self.finished = true;
+		let Some(record) = record else { return };
+
+		// Spectators which haven't seen the game end are told the player left.
+		if server.spectated.contains(&record.id) {
+			server.broadcasts.push((record.id, vec![Message::error(ErrorCode::SessionEnded, "The player left the game")]));
+		}
+
+		let (score, player, over) = (record.score, record.replay.player.clone(), record.entry.is_some());
+		if server.record_game(record) {
+			println!("Client {} disconnected with a new high score of {score} by {player}!", self.address);
+		}
+		else if over {
+			server.log(&format!("Client {} disconnected with a score of {score}", self.address));
+		}
+		else {
+			server.log(&format!("Client {} left before the game was over, with a score of {score}", self.address));
+		}
+	}
+
+
+	// Ends the session because the connection broke, or the client hung up.
+	fn disconnect(&mut self, reason: &str, server: &mut Server) {
+		if self.finished {
+			if matches!(self.state, State::Closing(_)) { self.state = State::Closed; }
+			return;
+		}
+		let address = self.address;
+		let record = match &mut self.state {
+			State::Json(session) | State::WebSocket(session, _) => {
+				session.handle(Received::Closed(reason.to_string()), address, server, &mut Vec::new());
+				session.record()
+			},
+			State::Text(session) => {
+				server.log(&format!("- {address} hung up"));
+				Some(session.player.record(&session.game))
+			},
+			_ => None,
+		};
+		self.finish(record, server);
+	}
+
+
+	// Sends as much of the output as the client accepts, and starts closing once the session is over.
+	fn flush(&mut self, server: &mut Server) {
+		while ! self.output.is_empty() {
+			match self.stream.write(&self.output) {
+				Ok(0) => break,
+				Ok(length) => {
+					self.output.drain(..length);
+					self.last_active = Instant::now();
+				},
+				Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+				Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+				Err(e) => {
+					self.output.clear();
+					self.disconnect(&e.to_string(), server);
+					self.state = State::Closed;
+					return;
+				},
+			}
+		}
+
+		/* Closing the socket while the client's input is still unread resets the connection, which can
+		discard the final messages before the client has read them. Instead, signal the end of the
+		stream and drain the input until the client hangs up. */
+		if self.finished && self.output.is_empty() && ! matches!(self.state, State::Closing(_) | State::Closed) {
+			let _ = self.stream.shutdown(Shutdown::Write);
+			self.state = State::Closing(Instant::now());
+		}
+	}
+
+
+	// The session watched by the client, if it's still spectating.
+	fn spectating(&self) -> Option<u64> {
+		match &self.state {
+			State::Json(session) | State::WebSocket(session, _) if ! self.finished => session.spectating,
+			_ => None,
+		}
+	}
+
+
+	// The session id of the client, if it's still waiting for or playing in a match.
+	fn member(&self) -> Option<u64> {
+		match &self.state {
+			State::Json(session) | State::WebSocket(session, _) if ! self.finished => session.member,
+			_ => None,
+		}
+	}
+
+
+	// Sends the messages of other sessions, to spectators and players of matches, until the game is over.
+	fn deliver(&mut self, messages: &[Message], server: &mut Server) {
+		if self.finished {
+			return;
+		}
+		let websocket = matches!(self.state, State::WebSocket(..));
+		self.send_messages(messages, websocket);
+		self.last_active = Instant::now();
+		if messages.iter().any(|message| matches!(message, Message::GameOver{..} | Message::Error{..})) {
+			if websocket {
+				self.output.extend(websocket::encode_frame(websocket::OPCODE_CLOSE, &[], false));
+			}
+			self.finish(None, server);
+		}
+	}
+
+
+	// The game played in the session, revealed for spectators.
+	fn reveal(&mut self, id: u64) -> Option<Vec<Message>> {
+		let (game, player) = match &mut self.state {
+			State::Json(session) | State::WebSocket(session, _) => session.game.as_mut().map(|(game, player)| (&*game, player))?,
+			State::Text(session) => (&session.game, &mut session.player),
+			_ => return None,
+		};
+		if player.id != id || self.finished {
+			return None;
+		}
+		return Some(spectate(game, player));
+	}
+
+
+	// Disconnects clients which have been idle for too long.
+	fn check_timeout(&mut self, server: &mut Server) {
+		if let State::Closing(since) = self.state {
+			if since.elapsed() >= Self::LINGER {
+				self.state = State::Closed;
+			}
+			return;
+		}
+		let Some(timeout) = server.options.idle_timeout else { return };
+		if self.last_active.elapsed() < timeout {
+			return;
+		}
+
+		// Give up on sending the rest of the output to clients which stopped reading it.
+		if self.finished {
+			server.log(&format!("- {} timed out before receiving the last replies", self.address));
+			self.state = State::Closed;
+			return;
+		}
+
+		let address = self.address;
+		let mut replies = Vec::new();
+		let record = match &mut self.state {
+			State::Json(session) | State::WebSocket(session, _) => {
+				session.handle(Received::TimedOut, address, server, &mut replies);
+				session.record()
+			},
+			State::Text(session) => {
+				server.log(&format!("- {address} timed out after being idle"));
+				Some(session.player.record(&session.game))
+			},
+			_ => None,
+		};
+		match self.state {
+			State::Json(_) => self.send_messages(&replies, false),
+			State::WebSocket(..) => {
+				self.send_messages(&replies, true);
+				self.output.extend(websocket::encode_frame(websocket::OPCODE_CLOSE, &[], false));
+			},
+			State::Text(_) => self.send_text("\nDisconnected after being idle for too long.\n"),
+			_ => (),
+		}
+		self.finish(record, server);
+	}
+
+}
+
+// --- Event loop
+
+/* Every connection is served by a single thread, which waits for any of the sockets to become ready
+and handles whatever they have sent. No connection can block the others, since all sockets are
+non-blocking, and every session is a state machine driven by the received messages. */
+fn run(mut server: Server, listeners: Vec<(TcpListener, Protocol)>) -> std::io::Result<()> {
+
+	const TICK: Duration = Duration::from_millis(250);
+
+	let mut connections: Vec<Connection> = Vec::new();
 	loop {
 
-		// Hide undiscovered information from the client.
-		let mut hidden_game = game.clone();
-		algorithms::hide_map(&mut hidden_game.map);
+		// Wait for new clients, messages or room to send the replies.
+		let mut fds: Vec<poll::PollFd> = listeners
+			.iter()
+			.map(|(listener, _)| poll::PollFd::new(listener, poll::READABLE))
+			.collect();
+		fds.extend(connections.iter().map(|connection| poll::PollFd::new(&connection.stream, connection.interest())));
+		poll::poll(&mut fds, TICK)?;
 
-		// Send the game state to the client.
-		if let Err(_) = serde_json::to_writer(&stream, &hidden_game) {
-			break;
+		// Serve the connected clients.
+		for (connection, fd) in connections.iter_mut().zip(&fds[listeners.len()..]) {
+			if fd.readable() {
+				connection.receive(&mut server);
+			}
+			connection.flush(&mut server);
+			connection.check_timeout(&mut server);
+			connection.flush(&mut server);
 		}
+		connections.retain(|connection| ! matches!(connection.state, State::Closed));
+		serve_matches(&mut connections, &mut server);
+		serve_spectators(&mut connections, &mut server);
+		server.save_leaderboard();
 
-		// Receive and perform action from the client.
-		if let Ok(action) = wumpus::Action::deserialize(&mut de) {
-			println!("- {client_address} performs: {action}");
-			game.do_action(action);
+		// Accept the new clients.
+		for ((listener, protocol), fd) in listeners.iter().zip(&fds) {
+			if ! fd.readable() {
+				continue;
+			}
+			while let Ok((stream, address)) = listener.accept() {
+
+				// Turn away clients once the server is full.
+				if connections.len() >= server.options.max_clients {
+					server.log(&format!("Client {address} rejected, the server is full"));
+					let rejection = match protocol {
+						Protocol::Json => serde_json::to_vec(&Message::error(ErrorCode::ServerFull, "The server is full, try again later"))
+							.expect("Unable to serialize the message"),
+						Protocol::Text => b"The server is full, try again later.\r\n".to_vec(),
+						Protocol::Http => http::Response::error(503, "The server is full, try again later").to_bytes(),
+					};
+					let _ = (&stream).write_all(&rejection);
+					continue;
+				}
+
+				if let Err(e) = stream.set_nonblocking(true) {
+					server.log(&format!("Client {address} could not be served: {e}"));
+					continue;
+				}
+				let _ = stream.set_nodelay(true);
+				let mut connection = Connection::new(stream, address, *protocol, &mut server);
+				connection.flush(&mut server);
+				connections.push(connection);
+			}
 		}
 	}
+}
 
-	// Update the global high score if applicable.
-	let player_score = game.score;
-	let mut high_score = high_score.lock().unwrap();
-	if player_score > *high_score {
-		*high_score = player_score;
-		println!("Client {client_address} disconnected with a new high score of {player_score}!");
+
+// Removes the players who left from the lobby and the matches, and sends the players their messages.
+fn serve_matches(connections: &mut [Connection], server: &mut Server) {
+
+	let members: HashMap<u64, usize> = connections
+		.iter()
+		.enumerate()
+		.filter_map(|(index, connection)| Some((connection.member()?, index)))
+		.collect();
+	let left: Vec<u64> = server.lobby
+		.iter()
+		.map(|waiting| waiting.id)
+		.chain(server.seats.keys().copied())
+		.filter(|id| ! members.contains_key(id))
+		.collect();
+	for id in left {
+		server.leave(id);
 	}
-	else {
-		println!("Client {client_address} disconnected with a score of {player_score}");
+
+	for (id, messages) in std::mem::take(&mut server.mail) {
+		if let Some(&index) = members.get(&id) {
+			connections[index].deliver(&messages, server);
+		}
 	}
 }
 
 
+// Sends the spectators the state of the sessions they watch.
+fn serve_spectators(connections: &mut [Connection], server: &mut Server) {
+
+	// New spectators first see the game as it is, unless the session doesn't exist.
+	for (id, address) in std::mem::take(&mut server.watching) {
+		let messages = connections
+			.iter_mut()
+			.find_map(|connection| connection.reveal(id))
+			.or_else(|| server.games.get_mut(&id).map(|session| spectate(&session.game, &mut session.player)))
+			.unwrap_or_else(|| {
+				// Matches can't be spectated, as every player would see the whole cave through another connection.
+				let message = match server.seats.contains_key(&id) || server.lobby.iter().any(|waiting| waiting.id == id) {
+					true => format!("Session {id} plays in a match, which can't be spectated"),
+					false => format!("There is no session {id} to spectate"),
+				};
+				vec![Message::error(ErrorCode::NoSuchSession, &message)]
+			});
+		if let Some(spectator) = connections.iter_mut().find(|connection| connection.address == address) {
+			spectator.deliver(&messages, server);
+		}
+	}
+
+	// Then they see every action, in order.
+	for (id, messages) in std::mem::take(&mut server.broadcasts) {
+		for spectator in connections.iter_mut().filter(|connection| connection.spectating() == Some(id)) {
+			spectator.deliver(&messages, server);
+		}
+	}
+
+	server.spectated = connections.iter().filter_map(Connection::spectating).collect();
+}
+
+
+fn bind(address: &str) -> TcpListener {
+	let listener = match TcpListener::bind(address) {
+		Ok(listener) => listener,
+		Err(e) => {
+			eprintln!("Error: Failed to bind to {address}: {e}");
+			std::process::exit(1);
+		},
+	};
+	if let Err(e) = listener.set_nonblocking(true) {
+		eprintln!("Error: Failed to listen on {address}: {e}");
+		std::process::exit(1);
+	}
+	return listener;
+}
+
+
 fn main() {
 
-	let address = concat!("127.0.0.1:", 6666);
-	let listener = TcpListener::bind(address).expect("Failed to bind to port");
-	println!("Server listening on {address}...");
+	let options = match Options::parse(std::env::args().skip(1)) {
+		Ok(Some(options)) => options,
+		Ok(None) => {
+			println!("{USAGE}");
+			return;
+		},
+		Err(message) => {
+			eprintln!("Error: {message}");
+			eprintln!("Run with --help for usage.");
+			std::process::exit(2);
+		},
+	};
 
-	let high_score = Arc::new(Mutex::new(std::i32::MIN));
+	// Serve the text protocol and HTTP API alongside, for telnet, netcat, browsers and scripts.
+	let ports = [
+		(Some(options.port), Protocol::Json, "clients"),
+		(options.text_port, Protocol::Text, "text clients"),
+		(options.http_port, Protocol::Http, "HTTP requests"),
+	];
+	let mut listeners = Vec::new();
+	for (port, protocol, name) in ports {
+		let Some(port) = port else { continue };
+		let address = format!("{}:{port}", options.bind);
+		listeners.push((bind(&address), protocol));
+		println!("Server listening for {name} on {address}...");
+	}
 
-	for stream in listener.incoming() {
-		let stream = stream.unwrap();
-		let high_score = Arc::clone(&high_score);
-		std::thread::spawn(move || handle_client(stream, high_score));
+	if let Some(directory) = &options.replays {
+		if let Err(e) = std::fs::create_dir_all(directory) {
+			eprintln!("Error: Unable to create the replay directory {directory}: {e}");
+			std::process::exit(1);
+		}
+	}
+	let leaderboard = match Leaderboard::load(&options.leaderboard) {
+		Ok(leaderboard) => leaderboard,
+		Err(message) => {
+			eprintln!("Error: {message}");
+			std::process::exit(1);
+		},
+	};
+	let secret = match options.daily_secret.as_deref().map(leaderboard::load_secret) {
+		Some(Ok(secret)) => secret,
+		Some(Err(message)) => {
+			eprintln!("Error: {message}");
+			std::process::exit(1);
+		},
+		None => rand::thread_rng().gen(),
+	};
+	if let Err(e) = run(Server::new(options, leaderboard, secret), listeners) {
+		eprintln!("Error: {e}");
+		std::process::exit(1);
 	}
 }
 
+// ---
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	// A quiet server which keeps everything in memory.
+	fn server() -> Server {
+		let args = ["--quiet", "--replays", ""].map(String::from);
+		let options = Options::parse(args.into_iter()).unwrap().unwrap();
+		return Server::new(options, Leaderboard::default(), rand::thread_rng().gen());
+	}
+
+	fn address() -> SocketAddr {
+		SocketAddr::from(([127, 0, 0, 1], 1234))
+	}
+
+	#[test]
+	fn skips_malformed_messages() {
+		let mut input = b"not json\n  {\"type\":\"Action\",\"action\":\"Walk\"}".to_vec();
+		assert!(matches!(parse_message(&mut input), Some(Received::Malformed(_))));
+		assert!(matches!(parse_message(&mut input), Some(Received::Message(Message::Action{action: wumpus::Action::Walk}))));
+		assert!(parse_message(&mut input).is_none());
+		assert!(input.is_empty());
+
+		// A broken object is skipped up to the next one, even on the same line.
+		let mut input = b"{\"type\":]{\"type\":\"Action\",\"action\":\"Dig\"}".to_vec();
+		assert!(matches!(parse_message(&mut input), Some(Received::Malformed(_))));
+		assert!(matches!(parse_message(&mut input), Some(Received::Message(Message::Action{action: wumpus::Action::Dig}))));
+
+		// Valid JSON which isn't a message is consumed entirely.
+		let mut input = b"{\"type\":\"Dance\"}{\"type\":\"Action\",\"action\":\"Left\"}".to_vec();
+		assert!(matches!(parse_message(&mut input), Some(Received::Invalid(_))));
+		assert!(matches!(parse_message(&mut input), Some(Received::Message(Message::Action{action: wumpus::Action::Left}))));
+	}
+
+	#[test]
+	fn waits_for_partial_messages() {
+		let message = b"{\"type\":\"Action\",\"action\":\"Walk\"}";
+		for end in 0..message.len() {
+			let mut input = message[..end].to_vec();
+			assert!(parse_message(&mut input).is_none(), "parsed {end} bytes");
+			assert_eq!(input, &message[..end], "consumed part of {end} bytes");
+		}
+
+		// Garbage before a partial message is skipped, and the message kept for later.
+		let mut input = b"oops\n{\"type\":\"Action\",".to_vec();
+		assert!(matches!(parse_message(&mut input), Some(Received::Malformed(_))));
+		assert!(parse_message(&mut input).is_none());
+		input.extend(b"\"action\":\"Walk\"}");
+		assert!(matches!(parse_message(&mut input), Some(Received::Message(Message::Action{action: wumpus::Action::Walk}))));
+	}
+
+	fn post(path: &str, body: &str) -> http::Request {
+		http::Request {
+			method : "POST".to_string(),
+			path   : path.to_string(),
+			body   : body.as_bytes().to_vec(),
+			.. Default::default()
+		}
+	}
+
+	#[test]
+	fn rejects_oversized_rules() {
+		let mut server = server();
+		for rules in [
+			r#"{"size_x": 100000, "size_y": 100000}"#,
+			r#"{"size_x": 40000, "size_y": 40000, "count_treasures": 1000000000}"#,
+			r#"{"count_treasures": 2147483647, "count_wumpuses": 2147483647}"#,
+		] {
+			let response = route(&post("/games", &format!(r#"{{"rules": {rules}}}"#)), &mut server);
+			assert_eq!(response.status, 400, "accepted {rules}");
+		}
+		assert!(server.games.is_empty());
+		assert_eq!(route(&post("/games", r#"{"rules": {"size_x": 8, "size_y": 8}}"#), &mut server).status, 201);
+	}
+
+	#[test]
+	fn counts_bad_messages_as_strikes() {
+		let mut server = server();
+		let mut session = JsonSession::new();
+		let mut replies = Vec::new();
+		let hello = Message::hello("test", None, "manual", Some(1), Mode::Single, false);
+		assert!(session.handle(Received::Message(hello), address(), &mut server, &mut replies));
+
+		// Every bad message is answered with an error and the state, until the last strike.
+		let mut input = b"garbage\n{\"type\":\"Dance\"}\n]\n".to_vec();
+		for strike in 1..server.options.max_strikes {
+			replies.clear();
+			let received = parse_message(&mut input).unwrap();
+			assert!(session.handle(received, address(), &mut server, &mut replies), "disconnected at strike {strike}");
+			assert!(matches!(replies[0], Message::Error{code: ErrorCode::MalformedMessage | ErrorCode::InvalidMessage, ..}));
+			assert!(matches!(replies[1], Message::State{..}));
+		}
+		replies.clear();
+		let received = parse_message(&mut input).unwrap();
+		assert!(! session.handle(received, address(), &mut server, &mut replies));
+		assert!(matches!(replies[..], [Message::Error{code: ErrorCode::TooManyErrors, ..}]));
+	}
+
+
+	#[test]
+	fn hides_the_map_of_the_daily_challenge() {
+		let mut server = server();
+		server.options.maps = [". . . T\n. . . .\nW . . .\nS . P .", ". . . .\n. . . T\nW . . .\nS . P .", ". . . .\n. . . .\nW . . T\nS . P ."]
+			.iter()
+			.enumerate()
+			.map(|(index, map)| (index.to_string(), map.parse().unwrap()))
+			.collect();
+		let today = server.daily_seed();
+		assert_eq!(server.choose_seed(None, true), Ok(Some(today)));
+		assert_eq!(server.choose_seed(Some(today + 1), false), Ok(Some(today + 1)));
+
+		// Any seed picking the map of the daily challenge would do to practise it.
+		for seed in [today, today.wrapping_add(3), today % 3] {
+			assert!(server.choose_seed(Some(seed), false).is_err(), "accepted the seed {seed}");
+			let response = route(&post("/games", &format!(r#"{{"seed": {seed}}}"#)), &mut server);
+			assert_eq!(response.status, 400);
+		}
+		let daily = server.new_game(Some(today)).map.to_string();
+		assert!((0..100).all(|_| server.new_game(None).map.to_string() != daily));
+	}
+
+	#[test]
+	fn unranks_spectated_games() {
+		let mut server = server();
+		for body in [r#"{"player": "watched"}"#, r#"{"player": "daily", "daily": true}"#, r#"{"player": "alone"}"#] {
+			assert_eq!(route(&post("/games", body), &mut server).status, 201);
+		}
+
+		// The daily challenge isn't shown to spectators until it's over, so it stays ranked.
+		server.watching.extend([(1, address()), (2, address())]);
+		serve_spectators(&mut [], &mut server);
+		for id in 1..=3 {
+			let session = server.games.get_mut(&id).unwrap();
+			session.game.game_over = true;
+			let record = session.player.record(&session.game);
+			server.record_game(record);
+		}
+		let rules = server.rules_played();
+		assert_eq!(server.leaderboard.top(&rules, Mode::Single, 10).iter().map(|entry| entry.player.as_str()).collect::<Vec<_>>(), ["alone"]);
+		assert_eq!(server.daily_entries(&rules, 10).len(), 1);
+	}
+
+}
@@ -0,0 +1,347 @@
+
+use wumpusworld::wumpus;
+use wumpusworld::models::{self, Model};
+use wumpusworld::multiplayer::SharedGame;
+
+use itertools::Itertools;
+
+
+const USAGE: &str = "\
+Usage: tournament [OPTIONS] ENTRANT...
+
+Plays every entrant on the same pool of seeded maps, first alone and then in a round-robin of
+competitive matches against every other entrant, and ranks them by their Elo rating and mean score.
+
+Entrants:
+  MODEL                  One of the bots of the client, random or bayes
+  MODEL:FILE             A bot with its parameters loaded from a JSON file
+  NAME=ENTRANT           Any of the above, under another name in the results
+
+Options:
+  --games N              The number of seeded maps in the pool [default: 100]
+  --first-seed SEED      The seed of the first map of the pool [default: 0]
+  --rules FILE           Load the game rules from a JSON file
+  --maps PATH            Play on the maps of a map file, or of every .txt or .map file in a directory, instead of seeded maps
+  --no-matches           Only play alone, skipping the competitive matches
+  --csv FILE             Write the results table to FILE as CSV
+  --markdown FILE        Write the results table to FILE as Markdown
+  -h, --help             Print this help text";
+
+
+const MAX_ACTIONS: usize = 1000;
+const INITIAL_RATING: f64 = 1500.0;
+const K_FACTOR: f64 = 32.0; // The most a rating changes after a single match.
+
+
+struct Entrant {
+	name   : String,
+	model  : String,
+	params : Option<models::ParamsBayes>,
+}
+
+impl Entrant {
+
+	// Parses an entrant such as `bayes`, `bayes:params.json` or `tuned=bayes:params.json`.
+	fn parse(spec: &str) -> Result<Self, String> {
+		let (name, bot) = spec.split_once('=').unwrap_or((spec, spec));
+		let (model, params) = match bot.split_once(':') {
+			Some((model, path)) => (model.to_lowercase(), Some(models::load_params(path)?)),
+			None => (bot.to_lowercase(), None),
+		};
+		match model.as_str() {
+			"random" if params.is_some() => Err("The random model takes no parameters".to_string()),
+			"random" | "bayes" => Ok(Self{name: name.to_string(), model, params}),
+			_ => Err(format!("Unknown model: {model}")),
+		}
+	}
+
+	// Creates the model of the entrant, whose random choices, if any, are drawn from the seed.
+	fn create_model(&self, seed: u64) -> Box<dyn Model> {
+		match self.model.as_str() {
+			"bayes" => Box::new(models::ModelBayes{params: self.params.clone().unwrap_or_default(), .. Default::default()}),
+			_ => Box::new(models::ModelRandom::seeded(seed)),
+		}
+	}
+
+}
+
+
+struct Options {
+	entrants   : Vec<Entrant>,
+	games      : u64,
+	first_seed : u64,
+	rules      : wumpus::Rules,
+	maps       : Vec<(String, wumpus::Map)>,
+	matches    : bool,
+	csv        : Option<String>,
+	markdown   : Option<String>,
+}
+
+impl Options {
+
+	// Parses the command line arguments, returning None if help was requested.
+	fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+
+		let mut options = Self {
+			entrants   : Vec::new(),
+			games      : 100,
+			first_seed : 0,
+			rules      : Default::default(),
+			maps       : Vec::new(),
+			matches    : true,
+			csv        : None,
+			markdown   : None,
+		};
+
+		while let Some(arg) = args.next() {
+
+			let mut value = || args
+				.next()
+				.ok_or(format!("Missing value for {arg}"));
+
+			match arg.as_str() {
+				"--games"       => options.games = value()?.parse().map_err(|_| "Invalid game count")?,
+				"--first-seed"  => options.first_seed = value()?.parse().map_err(|_| "Invalid seed")?,
+				"--rules"       => options.rules = wumpus::Rules::load(&value()?)?,
+				"--maps"        => options.maps = wumpus::Map::load_all(&value()?)?,
+				"--no-matches"  => options.matches = false,
+				"--csv"         => options.csv = Some(value()?),
+				"--markdown"    => options.markdown = Some(value()?),
+				"-h" | "--help" => return Ok(None),
+				_ if arg.starts_with('-') => return Err(format!("Unknown option: {arg}")),
+				_ => options.entrants.push(Entrant::parse(&arg)?),
+			}
+		}
+
+		if options.entrants.is_empty() {
+			return Err("No entrants specified".to_string());
+		}
+		if let Some(name) = options.entrants.iter().map(|entrant| &entrant.name).duplicates().next() {
+			return Err(format!("Duplicate entrant: {name}, name them with NAME=ENTRANT"));
+		}
+		if options.games == 0 {
+			return Err("At least one game is required".to_string());
+		}
+
+		options.rules.validate_maps(&options.maps)?;
+
+		// Matches need at least two entrants.
+		options.matches &= options.entrants.len() > 1;
+		return Ok(Some(options));
+	}
+
+
+	// The games every entrant plays, either on the loaded maps or on the seeded maps of the pool.
+	fn pool(&self) -> Vec<wumpus::Game> {
+		match self.maps.is_empty() {
+			true => (self.first_seed..self.first_seed + self.games)
+				.map(|seed| wumpus::Game::new_seeded(&self.rules, seed))
+				.collect(),
+			false => self.maps
+				.iter()
+				.map(|(_, map)| wumpus::Game::from_map(&self.rules.with_map(map), map.clone()))
+				.collect(),
+		}
+	}
+
+}
+
+
+// The results of an entrant over the whole tournament.
+#[derive(Default)]
+struct Standing {
+	scores : Vec<i32>, // The score on every map of the pool, played alone.
+	won    : u64,      // The maps on which every treasure was found.
+	regret : f64,      // The mean difference to the best score of any entrant on every map.
+	rating : f64,
+	wins   : u32,      // The outcome of the competitive matches.
+	draws  : u32,
+	losses : u32,
+}
+
+impl Standing {
+
+	fn mean_score(&self) -> f64 {
+		self.scores.iter().map(|&score| score as f64).sum::<f64>() / self.scores.len() as f64
+	}
+
+	fn win_rate(&self) -> f64 {
+		self.won as f64 / self.scores.len() as f64
+	}
+
+}
+
+
+// Updates the Elo ratings of two players after a match, the outcome being 1 if the first won, 0.5 for a draw and 0 if they lost.
+fn update_ratings(first: &mut f64, second: &mut f64, outcome: f64) {
+	let expected = 1.0 / (1.0 + 10f64.powf((*second - *first) / 400.0));
+	let change = K_FACTOR * (outcome - expected);
+	*first += change;
+	*second -= change;
+}
+
+
+// Plays every entrant alone on every map of the pool.
+fn play_alone(options: &Options, pool: &[wumpus::Game], standings: &mut [Standing]) {
+
+	for (entrant, standing) in options.entrants.iter().zip(standings.iter_mut()) {
+		let mut model = entrant.create_model(options.first_seed);
+		for game in pool {
+			let game = models::play(model.as_mut(), game.clone(), MAX_ACTIONS);
+			standing.scores.push(game.score);
+			if game.map.treasures.is_empty() { standing.won += 1; }
+		}
+	}
+
+	// The regret is how much worse an entrant did than the best entrant on the same map.
+	for game in 0..pool.len() {
+		let best = standings.iter().map(|standing| standing.scores[game]).max().unwrap_or_default();
+		for standing in standings.iter_mut() {
+			standing.regret += (best - standing.scores[game]) as f64 / pool.len() as f64;
+		}
+	}
+}
+
+
+// Plays a competitive match between every pair of entrants on every map of the pool, rating them as they go.
+fn play_matches(options: &Options, pool: &[wumpus::Game], standings: &mut [Standing]) {
+
+	for (map_index, map) in pool.iter().enumerate() {
+		for (first, second) in (0..options.entrants.len()).tuple_combinations() {
+
+			// Take turns at moving first, which may be an advantage.
+			let order = match map_index % 2 {
+				0 => [first, second],
+				_ => [second, first],
+			};
+			let mut players: Vec<Box<dyn Model>> = order.iter().map(|&index| options.entrants[index].create_model(options.first_seed + map_index as u64)).collect();
+			let names = order.iter().map(|&index| options.entrants[index].name.clone()).collect();
+			let game = SharedGame::new(map, names, false);
+			let game = models::play_shared(&mut players, game, MAX_ACTIONS);
+
+			// Compare the scores from the perspective of the first entrant of the pair.
+			let (score, other_score) = match map_index % 2 {
+				0 => (game.explorers[0].score, game.explorers[1].score),
+				_ => (game.explorers[1].score, game.explorers[0].score),
+			};
+			let outcome = match score.cmp(&other_score) {
+				std::cmp::Ordering::Greater => { standings[first].wins += 1; standings[second].losses += 1; 1.0 },
+				std::cmp::Ordering::Equal   => { standings[first].draws += 1; standings[second].draws += 1; 0.5 },
+				std::cmp::Ordering::Less    => { standings[first].losses += 1; standings[second].wins += 1; 0.0 },
+			};
+			let (head, tail) = standings.split_at_mut(second);
+			update_ratings(&mut head[first].rating, &mut tail[0].rating, outcome);
+		}
+	}
+}
+
+
+// The rows of the results table, best first, starting with the header.
+fn results_table(options: &Options, standings: &[Standing]) -> Vec<Vec<String>> {
+
+	let mut table = vec![
+		["Rank", "Entrant", "Mean score", "Win rate", "Mean regret", "Elo", "Wins", "Draws", "Losses"]
+			.map(str::to_string)
+			.to_vec(),
+	];
+	let ranking = (0..standings.len()).sorted_by(|&a, &b| match options.matches {
+		true => standings[b].rating.total_cmp(&standings[a].rating),
+		false => standings[b].mean_score().total_cmp(&standings[a].mean_score()),
+	});
+	for (rank, index) in ranking.enumerate() {
+		let standing = &standings[index];
+		let matches = |value: String| if options.matches { value } else { "-".to_string() };
+		table.push(vec![
+			(rank + 1).to_string(),
+			options.entrants[index].name.clone(),
+			format!("{:.2}", standing.mean_score()),
+			format!("{:.1}%", standing.win_rate() * 100.0),
+			format!("{:.2}", standing.regret),
+			matches(format!("{:.0}", standing.rating)),
+			matches(standing.wins.to_string()),
+			matches(standing.draws.to_string()),
+			matches(standing.losses.to_string()),
+		]);
+	}
+	return table;
+}
+
+
+fn to_markdown(table: &[Vec<String>]) -> String {
+	let row = |cells: &[String]| format!("| {} |", cells.join(" | "));
+	let alignment: Vec<String> = table[0]
+		.iter()
+		.map(|header| if header == "Entrant" { ":---" } else { "---:" }.to_string())
+		.collect();
+	std::iter::once(row(&table[0]))
+		.chain(std::iter::once(row(&alignment)))
+		.chain(table[1..].iter().map(|cells| row(cells)))
+		.map(|line| line + "\n")
+		.collect()
+}
+
+
+fn to_csv(table: &[Vec<String>]) -> String {
+	let escape = |cell: &String| match cell.contains([',', '"', '\n']) {
+		true => format!("\"{}\"", cell.replace('"', "\"\"")),
+		false => cell.clone(),
+	};
+	table
+		.iter()
+		.map(|cells| cells.iter().map(escape).join(",") + "\n")
+		.collect()
+}
+
+
+fn run(options: Options) -> Result<(), String> {
+
+	let mut standings: Vec<Standing> = options.entrants
+		.iter()
+		.map(|_| Standing{rating: INITIAL_RATING, .. Default::default()})
+		.collect();
+
+	let pool = options.pool();
+	let kind = if options.maps.is_empty() { "seeded" } else { "loaded" };
+	println!("Playing {} {kind} maps with each of the {} entrants...", pool.len(), options.entrants.len());
+	play_alone(&options, &pool, &mut standings);
+	if options.matches {
+		let pairs = options.entrants.len() * (options.entrants.len() - 1) / 2;
+		println!("Playing {} competitive matches between {pairs} pairs of entrants...", pairs * pool.len());
+		play_matches(&options, &pool, &mut standings);
+	}
+
+	let table = results_table(&options, &standings);
+	println!();
+	print!("{}", to_markdown(&table));
+
+	let write = |path: &String, contents: String| std::fs::write(path, contents).map_err(|e| format!("Unable to write {path}: {e}"));
+	if let Some(path) = &options.csv {
+		write(path, to_csv(&table))?;
+	}
+	if let Some(path) = &options.markdown {
+		write(path, to_markdown(&table))?;
+	}
+	Ok(())
+}
+
+
+fn main() {
+
+	let options = match Options::parse(std::env::args().skip(1)) {
+		Ok(Some(options)) => options,
+		Ok(None) => {
+			println!("{USAGE}");
+			return;
+		},
+		Err(message) => {
+			eprintln!("Error: {message}");
+			eprintln!("Run with --help for usage.");
+			std::process::exit(2);
+		},
+	};
+
+	if let Err(message) = run(options) {
+		eprintln!("Error: {message}");
+		std::process::exit(1);
+	}
+}
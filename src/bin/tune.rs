@@ -0,0 +1,126 @@
+
+use wumpusworld::wumpus;
+use wumpusworld::models;
+
+use rand::Rng;
+
+
+const USAGE: &str = "\
+Usage: tune [GAMES [SAMPLES]]
+
+Randomly searches the parameters of the bayes model, evaluating every parameter set over the same
+seeded maps, and prints the best one in the JSON format accepted by `client --params`.
+
+Arguments:
+  GAMES                  The number of seeded maps to evaluate every parameter set over [default: 100]
+  SAMPLES                The number of parameter sets to evaluate, including the defaults [default: 20]
+
+Options:
+  -h, --help             Print this help text";
+
+
+const MAX_ACTIONS: usize = 1000;
+
+
+struct Options {
+	games   : u64,
+	samples : u64,
+}
+
+impl Options {
+
+	// Parses the command line arguments, returning None if help was requested.
+	fn parse(args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+
+		let mut options = Self {
+			games   : 100,
+			samples : 20,
+		};
+
+		let mut positional = 0;
+		for arg in args {
+			match arg.as_str() {
+				"-h" | "--help" => return Ok(None),
+				_ if arg.starts_with('-') => return Err(format!("Unknown option: {arg}")),
+				_ => {
+					match positional {
+						0 => options.games = arg.parse().map_err(|_| "Invalid game count")?,
+						1 => options.samples = arg.parse().map_err(|_| "Invalid sample count")?,
+						_ => return Err(format!("Unexpected argument: {arg}")),
+					}
+					positional += 1;
+				},
+			}
+		}
+
+		if options.games == 0 {
+			return Err("At least one game is required".to_string());
+		}
+		return Ok(Some(options));
+	}
+
+}
+
+
+fn evaluate(params: &models::ParamsBayes, game_count: u64) -> f64 {
+	let mut total_score: i64 = 0;
+	let mut model = models::ModelBayes{params: params.clone(), .. Default::default()};
+	for seed in 0..game_count {
+		let game = models::play(&mut model, wumpus::Game::new_seeded(&Default::default(), seed), MAX_ACTIONS);
+		total_score += game.score as i64;
+	}
+	return total_score as f64 / game_count as f64;
+}
+
+
+fn main() {
+
+	let options = match Options::parse(std::env::args().skip(1)) {
+		Ok(Some(options)) => options,
+		Ok(None) => {
+			println!("{USAGE}");
+			return;
+		},
+		Err(message) => {
+			eprintln!("Error: {message}");
+			eprintln!("Run with --help for usage.");
+			std::process::exit(2);
+		},
+	};
+	let (game_count, sample_count) = (options.games, options.samples);
+
+	println!("Evaluating {sample_count} parameter sets over {game_count} seeded games each...");
+	let mut rng = rand::thread_rng();
+
+	// Start from the defaults, so that the result is never worse than them.
+	let mut best_params = models::ParamsBayes::default();
+	let mut best_score = evaluate(&best_params, game_count);
+	println!("default: mean score {best_score:.2}");
+
+	// Randomly search the parameter space.
+	for sample in 1..sample_count {
+		let params = models::ParamsBayes {
+			exploration        : match rng.gen_bool(0.5) {
+				true => models::Exploration::Heuristic,
+				false => models::Exploration::Information,
+			},
+			treasure_threshold : rng.gen_range(0.1..0.9),
+			wumpus_threshold   : rng.gen_range(0.5..=1.0),
+			cost_offset        : rng.gen_range(1..=100),
+			information_value  : rng.gen_range(0.0..50.0),
+			death_aversion     : rng.gen_range(0.0..50.0),
+		};
+
+		let score = evaluate(&params, game_count);
+		println!("{sample:>7}: mean score {score:.2}");
+		if score > best_score {
+			best_score = score;
+			best_params = params;
+		}
+	}
+
+	// Report the best parameters in the format accepted by `client --params`.
+	println!();
+	println!("Best mean score: {best_score:.2}");
+	println!("{}", serde_json::to_string_pretty(&best_params).expect("Error while serializing parameters"));
+}
@@ -0,0 +1,215 @@
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+/* A minimal HTTP/1.1 implementation, just enough to serve the game to browsers and scripts.
+Every response closes the connection, unless it upgrades to a WebSocket, so keep-alive and
+chunked bodies aren't supported. */
+
+// ---
+
+#[derive(Clone, Debug, Default)]
+pub struct Request {
+	pub method  : String,
+	pub path    : String,
+	pub headers : HashMap<String, String>, // Header names are lowercase.
+	pub body    : Vec<u8>,
+}
+
+impl Request {
+
+	const MAX_HEAD : usize = 1 << 14;
+	const MAX_BODY : usize = 1 << 16;
+
+	// Parses a request from the start of the input, returning it along with its length in bytes,
+	// or None if the input doesn't contain the entire request yet.
+	pub fn parse(input: &[u8]) -> Result<Option<(Self, usize)>, String> {
+
+		// The head is terminated by an empty line.
+		let Some(head_length) = input.windows(4).position(|window| window == b"\r\n\r\n") else {
+			if input.len() > Self::MAX_HEAD {
+				return Err("The request head is too large".to_string());
+			}
+			return Ok(None);
+		};
+		let head = std::str::from_utf8(&input[..head_length]).map_err(|_| "The request head isn't valid UTF-8")?;
+		let mut lines = head.split("\r\n");
+
+		// The request line, e.g. `GET /games/1 HTTP/1.1`.
+		let request_line = lines.next().unwrap_or_default();
+		let mut parts = request_line.split_whitespace();
+		let (Some(method), Some(path), Some(_version)) = (parts.next(), parts.next(), parts.next()) else {
+			return Err(format!("Malformed request line: {request_line}"));
+		};
+
+		let mut request = Self {
+			method : method.to_string(),
+			path   : path.to_string(),
+			.. Default::default()
+		};
+		for line in lines {
+			let (name, value) = line.split_once(':').ok_or(format!("Malformed header: {line}"))?;
+			request.headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+		}
+
+		// The body, if any.
+		let body_length: usize = match request.header("content-length") {
+			Some(length) => length.parse().map_err(|_| "Invalid Content-Length")?,
+			None => 0,
+		};
+		if body_length > Self::MAX_BODY {
+			return Err("The request body is too large".to_string());
+		}
+		let length = head_length + 4 + body_length;
+		if input.len() < length {
+			return Ok(None);
+		}
+		request.body = input[head_length + 4..length].to_vec();
+
+		return Ok(Some((request, length)));
+	}
+
+
+	pub fn header(&self, name: &str) -> Option<&str> {
+		self.headers.get(name).map(String::as_str)
+	}
+
+
+	// The path split into its segments, without the query string.
+	pub fn segments(&self) -> Vec<&str> {
+		let path = self.path.split('?').next().unwrap_or_default();
+		path.split('/').filter(|segment| ! segment.is_empty()).collect()
+	}
+
+
+	// The value of a parameter in the query string, e.g. `count` in `/highscores?count=10`.
+	pub fn query(&self, name: &str) -> Option<&str> {
+		let (_, query) = self.path.split_once('?')?;
+		query
+			.split('&')
+			.filter_map(|parameter| parameter.split_once('='))
+			.find(|(key, _)| *key == name)
+			.map(|(_, value)| value)
+	}
+
+}
+
+// ---
+
+#[derive(Clone, Debug)]
+pub struct Response {
+	pub status  : u16,
+	pub headers : Vec<(String, String)>,
+	pub body    : Vec<u8>,
+}
+
+impl Response {
+
+	pub fn new(status: u16, content_type: &str, body: impl Into<Vec<u8>>) -> Self {
+		Self {
+			status,
+			headers : vec![("Content-Type".to_string(), content_type.to_string())],
+			body    : body.into(),
+		}
+	}
+
+	pub fn json(status: u16, value: &impl Serialize) -> Self {
+		let body = serde_json::to_vec(value).expect("Unable to serialize the response");
+		return Self::new(status, "application/json", body);
+	}
+
+	// An error response, with the message wrapped in a JSON object.
+	pub fn error(status: u16, message: &str) -> Self {
+		return Self::json(status, &serde_json::json!({"error": message}));
+	}
+
+
+	pub fn with_header(mut self, name: &str, value: &str) -> Self {
+		self.headers.push((name.to_string(), value.to_string()));
+		return self;
+	}
+
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+		for (name, value) in &self.headers {
+			head.push_str(&format!("{name}: {value}\r\n"));
+		}
+
+		// Switching protocols keeps the connection open for the new protocol.
+		if self.status != 101 {
+			head.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n", self.body.len()));
+		}
+		head.push_str("\r\n");
+
+		let mut bytes = head.into_bytes();
+		bytes.extend(&self.body);
+		return bytes;
+	}
+
+}
+
+fn reason(status: u16) -> &'static str {
+	match status {
+		101 => "Switching Protocols",
+		200 => "OK",
+		201 => "Created",
+		400 => "Bad Request",
+		404 => "Not Found",
+		405 => "Method Not Allowed",
+		409 => "Conflict",
+		503 => "Service Unavailable",
+		_   => "Unknown",
+	}
+}
+
+// ---
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	#[test]
+	fn parses_request_with_body() {
+		let input = b"POST /games/3/actions?count=5&daily=true HTTP/1.1\r\nHost: localhost\r\nContent-Length: 17\r\n\r\n{\"action\":\"Walk\"}GET";
+		let (request, length) = Request::parse(input).unwrap().unwrap();
+		assert_eq!(length, input.len() - 3);
+		assert_eq!(request.method, "POST");
+		assert_eq!(request.segments(), ["games", "3", "actions"]);
+		assert_eq!(request.query("count"), Some("5"));
+		assert_eq!(request.query("daily"), Some("true"));
+		assert_eq!(request.query("seed"), None);
+		assert_eq!(request.header("host"), Some("localhost"));
+		assert_eq!(request.body, b"{\"action\":\"Walk\"}");
+	}
+
+	#[test]
+	fn waits_for_partial_requests() {
+		let input = b"POST /games HTTP/1.1\r\nContent-Length: 4\r\n\r\n{}{}";
+		for end in 0..input.len() {
+			assert!(Request::parse(&input[..end]).unwrap().is_none(), "parsed {end} bytes");
+		}
+		assert!(Request::parse(input).unwrap().is_some());
+	}
+
+	#[test]
+	fn rejects_malformed_requests() {
+		assert!(Request::parse(b"GET\r\n\r\n").is_err());
+		assert!(Request::parse(b"GET / HTTP/1.1\r\nNoColon\r\n\r\n").is_err());
+		assert!(Request::parse(b"GET / HTTP/1.1\r\nContent-Length: many\r\n\r\n").is_err());
+		assert!(Request::parse(format!("GET / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", Request::MAX_BODY + 1).as_bytes()).is_err());
+		assert!(Request::parse(&vec![b'a'; Request::MAX_HEAD + 1]).is_err());
+	}
+
+	#[test]
+	fn serializes_responses() {
+		let response = String::from_utf8(Response::error(404, "No such game").to_bytes()).unwrap();
+		assert_eq!(response, "HTTP/1.1 404 Not Found\r\nContent-Type: application/json\r\nContent-Length: 24\r\nConnection: close\r\n\r\n{\"error\":\"No such game\"}");
+
+		let upgrade = String::from_utf8(Response{status: 101, headers: Vec::new(), body: Vec::new()}.with_header("Upgrade", "websocket").to_bytes()).unwrap();
+		assert_eq!(upgrade, "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\r\n");
+	}
+
+}
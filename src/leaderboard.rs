@@ -0,0 +1,338 @@
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use serde::{Serialize, Deserialize};
+
+use crate::protocol::Mode;
+use crate::wumpus::Rules;
+
+// ---
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+	pub player    : String,
+	pub model     : String,
+	pub score     : i32,
+	pub seed      : Option<u64>, // The seed of the map, if it was generated from one.
+	pub actions   : u32,
+	pub timestamp : u64,         // Seconds since the Unix epoch.
+	pub rules     : Rules,
+	#[serde(default)]
+	pub daily     : bool,        // Played as the daily challenge of its seed, ranked apart from other games.
+	#[serde(default)]
+	pub mode      : Mode,        // Matches are ranked apart from single player games, and each other.
+}
+
+impl Entry {
+
+	pub fn now() -> u64 {
+		std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)
+			.map_or(0, |duration| duration.as_secs())
+	}
+
+}
+
+// ---
+
+/* The daily challenge has every player play the same map on the same day, generated from a seed derived
+from the date in UTC. The seed is drawn from a random number generator keyed with a secret of the server
+mixed with the day number, so the maps of consecutive days have nothing in common, and the map of today
+can't be known before it's played, even from the seeds of the days before. */
+
+pub type Secret = [u8; 32];
+
+// The number of days since the Unix epoch, in UTC.
+pub fn today() -> u64 {
+	Entry::now() / (24 * 60 * 60)
+}
+
+pub fn daily_seed(secret: &Secret, day: u64) -> u64 {
+	let mut key = *secret;
+	key.iter_mut().zip(day.to_le_bytes()).for_each(|(byte, day)| *byte ^= day);
+	return StdRng::from_seed(key).gen();
+}
+
+// Loads the secret from a file as hexadecimal, generating it and creating the file if it doesn't exist yet.
+pub fn load_secret(path: &str) -> Result<Secret, String> {
+	match std::fs::read_to_string(path) {
+		Ok(contents) => {
+			let hex = contents.trim();
+			let bytes: Option<Vec<u8>> = (0..hex.len())
+				.step_by(2)
+				.map(|i| hex.get(i..i + 2).and_then(|digits| u8::from_str_radix(digits, 16).ok()))
+				.collect();
+			return bytes
+				.and_then(|bytes| Secret::try_from(bytes).ok())
+				.ok_or(format!("Invalid secret {path}: expected {} hexadecimal digits", 2 * std::mem::size_of::<Secret>()));
+		},
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+		Err(e) => return Err(format!("Unable to read {path}: {e}")),
+	}
+
+	// Only the owner may read the secret, which would give away the daily challenges otherwise.
+	let secret: Secret = rand::thread_rng().gen();
+	let hex: String = secret.iter().map(|byte| format!("{byte:02x}")).collect();
+	let write = || -> std::io::Result<()> {
+		let mut options = std::fs::OpenOptions::new();
+		options.write(true).create_new(true);
+		#[cfg(unix)]
+		std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+		writeln!(options.open(path)?, "{hex}")
+	};
+	write().map_err(|e| format!("Unable to create the secret {path}: {e}"))?;
+	return Ok(secret);
+}
+
+// Formats a day number as a date such as 2024-03-01.
+pub fn format_day(day: u64) -> String {
+
+	// Count in eras of 400 years starting in March, so leap days fall at the end of the years.
+	let days = day as i64 + 719_468;
+	let era = days.div_euclid(146_097);
+	let day_of_era = days.rem_euclid(146_097);
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let month = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * month + 2) / 5 + 1;
+	let month = if month < 10 { month + 3 } else { month - 9 };
+	let year = year_of_era + era * 400 + i64::from(month <= 2);
+	return format!("{year:04}-{month:02}-{day:02}");
+}
+
+// ---
+
+// The best scores of every rule set, kept in a JSON file.
+#[derive(Clone, Debug, Default)]
+pub struct Leaderboard {
+	path    : Option<PathBuf>, // Only kept in memory if there's no file.
+	entries : Vec<Entry>,      // Sorted from the highest score.
+}
+
+impl Leaderboard {
+
+	pub const MAX_ENTRIES: usize = 100; // The number of entries kept per rule set, mode and daily challenge.
+
+	// Loads the leaderboard from a file, starting an empty one if it doesn't exist yet.
+	pub fn load(path: &str) -> Result<Self, String> {
+		let entries: Vec<Entry> = match std::fs::read_to_string(path) {
+			Ok(contents) => serde_json::from_str(&contents).map_err(|e| format!("Invalid leaderboard {path}: {e}"))?,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+			Err(e) => return Err(format!("Unable to read {path}: {e}")),
+		};
+		let mut leaderboard = Self{path: Some(PathBuf::from(path)), entries};
+		leaderboard.entries.sort_by_key(|entry| std::cmp::Reverse(entry.score));
+		return Ok(leaderboard);
+	}
+
+
+	// Records the entry, returning whether it's the best score of its rule set. Call save to keep it.
+	pub fn record(&mut self, entry: Entry) -> bool {
+
+		let rules = std::slice::from_ref(&entry.rules);
+		let board = match entry.daily {
+			true => self.daily(rules, entry.seed.unwrap_or_default(), 1),
+			false => self.top(rules, entry.mode, 1),
+		};
+		let best = board.first().is_none_or(|top| entry.score > top.score);
+
+		// Insert the entry after any equal scores, so earlier entries keep their rank.
+		let index = self.entries.partition_point(|other| other.score >= entry.score);
+		self.entries.insert(index, entry);
+
+		// Forget the lowest scores of rule sets, modes or daily challenges with too many entries.
+		let mut counts: Vec<(Rules, Mode, Option<u64>, usize)> = Vec::new();
+		self.entries.retain(|entry| {
+			let daily = entry.seed.filter(|_| entry.daily);
+			let index = match counts.iter().position(|(rules, mode, seed, _)| *rules == entry.rules && *mode == entry.mode && *seed == daily) {
+				Some(index) => index,
+				None => { counts.push((entry.rules.clone(), entry.mode, daily, 0)); counts.len() - 1 },
+			};
+			counts[index].3 += 1;
+			counts[index].3 <= Self::MAX_ENTRIES
+		});
+
+		return best;
+	}
+
+
+	// The best entries played with any of the given rules and the mode, besides the daily challenges.
+	pub fn top(&self, rules: &[Rules], mode: Mode, count: usize) -> Vec<Entry> {
+		self.entries
+			.iter()
+			.filter(|entry| rules.contains(&entry.rules) && entry.mode == mode && ! entry.daily)
+			.take(count)
+			.cloned()
+			.collect()
+	}
+
+
+	// The best entries of the daily challenge with the given seed, played with any of the given rules.
+	pub fn daily(&self, rules: &[Rules], seed: u64, count: usize) -> Vec<Entry> {
+		self.entries
+			.iter()
+			.filter(|entry| rules.contains(&entry.rules) && entry.daily && entry.seed == Some(seed))
+			.take(count)
+			.cloned()
+			.collect()
+	}
+
+
+	/* Writes the leaderboard to a temporary file next to it, and then renames it over the old one.
+	Renaming is atomic, so the file is never left half-written, even if the server crashes. */
+	pub fn save(&self) -> Result<(), String> {
+		let Some(path) = &self.path else { return Ok(()) };
+		let temporary = path.with_extension(format!("tmp.{}", std::process::id()));
+
+		let contents = serde_json::to_string_pretty(&self.entries).map_err(|e| e.to_string())?;
+		let write = || -> std::io::Result<()> {
+			let mut file = std::fs::File::create(&temporary)?;
+			file.write_all(contents.as_bytes())?;
+			file.sync_all()?;
+			std::fs::rename(&temporary, path)
+		};
+		write().map_err(|e| {
+			let _ = std::fs::remove_file(&temporary);
+			format!("Unable to save the leaderboard to {}: {e}", path.display())
+		})
+	}
+
+}
+
+// ---
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	fn entry(player: &str, score: i32, rules: &Rules) -> Entry {
+		Entry {
+			player    : player.to_string(),
+			model     : "bayes".to_string(),
+			score,
+			seed      : Some(1),
+			actions   : 10,
+			timestamp : 0,
+			rules     : rules.clone(),
+			daily     : false,
+			mode      : Mode::Single,
+		}
+	}
+
+	fn players(entries: &[Entry]) -> Vec<&str> {
+		entries.iter().map(|entry| entry.player.as_str()).collect()
+	}
+
+	// A directory of its own for every test, emptied first.
+	fn temporary_directory(name: &str) -> PathBuf {
+		let directory = std::env::temp_dir().join(format!("wumpusworld-{name}-{}", std::process::id()));
+		let _ = std::fs::remove_dir_all(&directory);
+		std::fs::create_dir_all(&directory).unwrap();
+		return directory;
+	}
+
+	#[test]
+	fn ranks_every_board_apart() {
+		let rules = Rules::default();
+		let small = Rules{size_x: 3, size_y: 3, .. Default::default()};
+		let mut leaderboard = Leaderboard::default();
+
+		assert!(leaderboard.record(entry("alice", 100, &rules)));
+		assert!(leaderboard.record(entry("bob", 50, &small)), "the best score of its own rules");
+		assert!(leaderboard.record(Entry{mode: Mode::Competitive, .. entry("carol", 20, &rules)}));
+		assert!(leaderboard.record(Entry{daily: true, seed: Some(7), .. entry("dave", 10, &rules)}));
+		assert!(! leaderboard.record(entry("erin", 80, &rules)));
+		assert!(! leaderboard.record(entry("frank", 100, &rules)), "a tie isn't a new best");
+		assert!(leaderboard.record(entry("grace", 101, &rules)));
+
+		assert_eq!(players(&leaderboard.top(std::slice::from_ref(&rules), Mode::Single, 10)), ["grace", "alice", "frank", "erin"]);
+		assert_eq!(players(&leaderboard.top(std::slice::from_ref(&rules), Mode::Single, 2)), ["grace", "alice"]);
+		assert_eq!(players(&leaderboard.top(std::slice::from_ref(&small), Mode::Single, 10)), ["bob"]);
+		assert_eq!(players(&leaderboard.top(&[small, rules.clone()], Mode::Single, 3)), ["grace", "alice", "frank"]);
+		assert_eq!(players(&leaderboard.top(std::slice::from_ref(&rules), Mode::Competitive, 10)), ["carol"]);
+		assert_eq!(players(&leaderboard.top(std::slice::from_ref(&rules), Mode::Cooperative, 10)), Vec::<&str>::new());
+		assert_eq!(players(&leaderboard.daily(std::slice::from_ref(&rules), 7, 10)), ["dave"]);
+		assert!(leaderboard.daily(std::slice::from_ref(&rules), 8, 10).is_empty());
+	}
+
+	#[test]
+	fn prunes_the_lowest_scores_of_full_boards() {
+		let rules = Rules::default();
+		let small = Rules{size_x: 3, size_y: 3, .. Default::default()};
+		let mut leaderboard = Leaderboard::default();
+		leaderboard.record(entry("small", 0, &small));
+		leaderboard.record(Entry{daily: true, .. entry("daily", 0, &rules)});
+		for score in 0..Leaderboard::MAX_ENTRIES as i32 + 5 {
+			leaderboard.record(entry(&score.to_string(), score, &rules));
+		}
+
+		// Only the board which overflowed loses entries, and those are its lowest.
+		let top = leaderboard.top(std::slice::from_ref(&rules), Mode::Single, usize::MAX);
+		assert_eq!(top.len(), Leaderboard::MAX_ENTRIES);
+		assert_eq!(top.last().unwrap().score, 5);
+		assert_eq!(players(&leaderboard.top(std::slice::from_ref(&small), Mode::Single, 10)), ["small"]);
+		assert_eq!(players(&leaderboard.daily(std::slice::from_ref(&rules), 1, 10)), ["daily"]);
+	}
+
+	#[test]
+	fn saves_and_loads() {
+		let directory = temporary_directory("leaderboard");
+		let path = directory.join("leaderboard.json");
+		let path = path.to_str().unwrap();
+		let rules = Rules::default();
+
+		let mut leaderboard = Leaderboard::load(path).unwrap();
+		assert!(leaderboard.top(std::slice::from_ref(&rules), Mode::Single, 10).is_empty(), "a missing file starts an empty leaderboard");
+		leaderboard.record(entry("alice", 100, &rules));
+		leaderboard.record(entry("bob", 200, &rules));
+		leaderboard.record(entry("carol", 100, &rules));
+		leaderboard.save().unwrap();
+		leaderboard.record(entry("dave", 300, &rules));
+		leaderboard.save().unwrap();
+
+		// The file is replaced as a whole, without leaving the temporary file behind, and keeps the tie order.
+		let files: Vec<_> = std::fs::read_dir(&directory).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+		assert_eq!(files, ["leaderboard.json"]);
+		let loaded = Leaderboard::load(path).unwrap();
+		assert_eq!(loaded.top(std::slice::from_ref(&rules), Mode::Single, 10), leaderboard.top(std::slice::from_ref(&rules), Mode::Single, 10));
+		assert_eq!(players(&loaded.top(std::slice::from_ref(&rules), Mode::Single, 10)), ["dave", "bob", "alice", "carol"]);
+
+		std::fs::write(path, "not json").unwrap();
+		assert!(Leaderboard::load(path).is_err());
+		std::fs::remove_dir_all(&directory).unwrap();
+	}
+
+
+	#[test]
+	fn derives_daily_seeds_from_the_secret() {
+		let secret: Secret = [7; 32];
+		let mut other = secret;
+		other[31] ^= 1;
+		assert_eq!(daily_seed(&secret, 20_000), daily_seed(&secret, 20_000));
+		assert_ne!(daily_seed(&secret, 20_000), daily_seed(&secret, 20_001));
+		assert_ne!(daily_seed(&secret, 20_000), daily_seed(&other, 20_000));
+	}
+
+	#[test]
+	fn generates_the_secret_once() {
+		let directory = temporary_directory("secret");
+		let path = directory.join("daily-secret");
+		let path = path.to_str().unwrap();
+
+		let secret = load_secret(path).unwrap();
+		assert_eq!(load_secret(path), Ok(secret));
+		#[cfg(unix)]
+		assert_eq!(std::os::unix::fs::PermissionsExt::mode(&std::fs::metadata(path).unwrap().permissions()) & 0o777, 0o600);
+
+		for contents in ["", "00", &"0".repeat(65), &"g".repeat(64)] {
+			std::fs::write(path, contents).unwrap();
+			assert!(load_secret(path).is_err(), "loaded {contents:?}");
+		}
+		std::fs::remove_dir_all(&directory).unwrap();
+	}
+
+}
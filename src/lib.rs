@@ -1,3 +1,11 @@
 pub mod algorithms;
 pub mod wumpus;
 pub mod models;
+pub mod tui;
+pub mod protocol;
+pub mod leaderboard;
+pub mod replay;
+pub mod multiplayer;
+pub mod http;
+pub mod websocket;
+pub mod poll;
@@ -3,7 +3,12 @@ use std::io::{BufRead, Write};
 use std::str::FromStr;
 use std::collections::{HashSet, HashMap};
 
+use itertools::Itertools;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+
 use crate::algorithms;
+use crate::multiplayer::{PlayerStatus, SharedGame, TeamMessage};
 use crate::wumpus::{
 	Coordinate,
 	Class,
@@ -14,26 +19,229 @@ use crate::wumpus::{
 
 
 pub trait Model {
+
+	// Called before the first action of every game.
+	fn new_game(&mut self) {}
+
+	// Called to choose the next action.
 	fn run(&mut self, game: &Game) -> Action;
+
+	// Called with the resulting state after every action.
+	fn observe(&mut self, _action: &Action, _game: &Game) {}
+
+	// Called with the final state once the game has ended.
+	fn game_over(&mut self, _game: &Game) {}
+
+	// Called to describe the reasoning behind the last chosen action.
+	fn explain(&self) -> Option<Explanation> { None }
+
+	/* The multi-agent extension, used when playing in a team. Before choosing every action, the model
+	observes its teammates along with the messages they sent since, and after choosing it the model
+	may send a message to them, e.g. to claim the location it is heading for. */
+
+	// Called before choosing an action in a team, with the other players and their new messages.
+	fn observe_team(&mut self, _teammates: &[PlayerStatus], _messages: &[TeamMessage]) {}
+
+	// Called after choosing an action in a team, to send a message to the other players.
+	fn team_message(&mut self) -> Option<TeamMessage> { None }
+}
+
+// ---
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Intent {
+	Dig(Coordinate),     // Walk to the location and dig for a treasure.
+	Shoot(Coordinate),   // Walk next to the location and shoot into it.
+	Explore(Coordinate), // Walk to the location to discover it.
+}
+
+impl Intent {
+
+	pub fn location(&self) -> Coordinate {
+		match *self {
+			Intent::Dig(location) | Intent::Shoot(location) | Intent::Explore(location) => location,
+		}
+	}
+
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Belief {
+	pub location      : Coordinate,
+	pub probabilities : ClassField<f64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Candidate {
+	pub location : Coordinate,
+	pub score    : f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Explanation {
+	pub beliefs    : Vec<Belief>,    // The class probabilities of every uncertain location.
+	pub candidates : Vec<Candidate>, // The locations considered for the intent, best first.
+	pub intent     : Intent,         // The chosen abstract action.
+	pub queue      : Vec<Action>,    // The actions left to perform the intent.
+}
+
+impl std::fmt::Display for Explanation {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let intent = match self.intent {
+			Intent::Dig(location)     => format!("dig at {location}"),
+			Intent::Shoot(location)   => format!("shoot at {location}"),
+			Intent::Explore(location) => format!("explore {location}"),
+		};
+		writeln!(f, "Intent: {intent}, then: {}", self.queue.iter().join(","))?;
+		for belief in &self.beliefs {
+			let p = belief.probabilities;
+			writeln!(f, "  {}: treasure {:.2}, wumpus {:.2}, pit {:.2}", belief.location, p.treasure, p.wumpus, p.pit)?;
+		}
+		let candidates = self.candidates
+			.iter()
+			.map(|candidate| format!("{} {:.2}", candidate.location, candidate.score))
+			.join(", ");
+		write!(f, "Candidates: {candidates}")
+	}
+}
+
+
+// The tunable parameters of a model, which can be loaded from JSON and set by name.
+pub trait Params: Serialize + DeserializeOwned {
+	fn validate(&self) -> Result<(), String>;
+}
+
+
+pub fn load_params<P: Params>(path: &str) -> Result<P, String> {
+	let file = std::fs::File::open(path).map_err(|e| format!("Unable to open {path}: {e}"))?;
+	let params: P = serde_json::from_reader(file).map_err(|e| format!("Invalid parameters in {path}: {e}"))?;
+	params.validate().map_err(|e| format!("Invalid parameters in {path}: {e}"))?;
+	Ok(params)
+}
+
+
+pub fn set_param<P: Params>(params: &mut P, assignment: &str) -> Result<(), String> {
+
+	let (key, value) = assignment
+		.split_once('=')
+		.ok_or(format!("Expected key=value, got: {assignment}"))?;
+
+	// Round-trip the parameters through JSON to set the field by name.
+	let mut json = serde_json::to_value(&*params).map_err(|e| e.to_string())?;
+	let field = json
+		.get_mut(key)
+		.ok_or(format!("Unknown parameter: {key}"))?;
+
+	// Values which aren't valid JSON, such as enum variant names, are taken as strings.
+	*field = serde_json::from_str(value).unwrap_or(serde_json::Value::String(value.to_string()));
+	let new_params: P = serde_json::from_value(json).map_err(|e| format!("Invalid value for {key}: {e}"))?;
+	new_params.validate().map_err(|e| format!("Invalid value for {key}: {e}"))?;
+	*params = new_params;
+	Ok(())
+}
+
+
+pub fn play(model: &mut dyn Model, mut game: Game, max_actions: usize) -> Game {
+
+	let hide = |game: &Game| -> Game {
+		let mut hidden_game = game.clone();
+		algorithms::hide_map(&mut hidden_game.map);
+		hidden_game
+	};
+
+	model.new_game();
+	for _ in 0..max_actions {
+		if game.game_over { break; }
+		let action = model.run(&hide(&game));
+		game.do_action(action);
+		model.observe(&action, &hide(&game));
+	}
+	model.game_over(&hide(&game));
+	return game;
+}
+
+
+// Plays a shared game, with a model for every player, relaying the messages of teams between them.
+pub fn play_shared(models: &mut [Box<dyn Model>], mut game: SharedGame, max_actions: usize) -> SharedGame {
+
+	let hide = |game: &SharedGame, index: usize| -> Game {
+		let mut view = game.view(index);
+		algorithms::hide_map(&mut view.map);
+		view
+	};
+
+	models.iter_mut().for_each(|model| model.new_game());
+	let mut last_actions: Vec<Option<Action>> = vec![None; models.len()];
+	let mut inboxes: Vec<Vec<TeamMessage>> = vec![Vec::new(); models.len()];
+	for _ in 0..max_actions {
+		if game.game_over { break; }
+
+		// Like over the network, the players observe the outcome of their last action on their next turn.
+		let index = game.turn;
+		let view = hide(&game, index);
+		if let Some(action) = last_actions[index] {
+			models[index].observe(&action, &view);
+		}
+		if game.team {
+			models[index].observe_team(&game.others(index), &inboxes[index]);
+			inboxes[index].clear();
+		}
+
+		let action = models[index].run(&view);
+		if let Some(mut message) = models[index].team_message().filter(|_| game.team) {
+			message.from = game.explorers[index].name.clone();
+			for (other, inbox) in inboxes.iter_mut().enumerate() {
+				if other != index { inbox.push(message.clone()); }
+			}
+		}
+		game.do_action(action);
+		last_actions[index] = Some(action);
+	}
+	for (index, model) in models.iter_mut().enumerate() {
+		model.game_over(&hide(&game, index));
+	}
+	return game;
 }
 
 // ---
 
 pub struct ModelRandom{
+	pub rng: StdRng,
+}
+
+impl ModelRandom {
+
+	// Performs the same actions in every run, so that games against it can be reproduced.
+	pub fn seeded(seed: u64) -> Self {
+		Self{rng: StdRng::seed_from_u64(seed)}
+	}
+
+}
+
+impl Default for ModelRandom {
+	fn default() -> Self {
+		Self{rng: StdRng::from_entropy()}
+	}
 }
 
 impl Model for ModelRandom {
 	fn run(&mut self, _game: &Game) -> Action {
-		rand::random()
+		self.rng.gen()
 	}
 }
 
 // ---
 
+#[derive(Default)]
 pub struct ModelManual {
+	pub message: Option<TeamMessage>, // Typed with `say`, sent to the team along with the next action.
 }
 
 impl Model for ModelManual {
+	fn team_message(&mut self) -> Option<TeamMessage> {
+		self.message.take()
+	}
+
 	fn run(&mut self, _game: &Game) -> Action {
 		loop {
 
@@ -57,6 +265,11 @@ impl Model for ModelManual {
 				;
 
 			if let Ok(action) = Action::from_str(line.as_str()) { break action }
+			if let Some(text) = line.strip_prefix("say ") {
+				self.message = Some(TeamMessage{from: String::new(), text: text.to_string(), claim: None});
+				println!("Your team will receive the message along with your next action.");
+				continue;
+			}
 			println!("Unrecognized action. Try again.");
 		}
 	}
@@ -64,16 +277,76 @@ impl Model for ModelManual {
 
 // ---
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Exploration {
+	Heuristic,   // Prefer close and safe locations.
+	#[default]
+	Information, // Prefer locations revealing the most about the treasures.
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParamsBayes {
+	pub exploration        : Exploration,
+	pub treasure_threshold : f64, // The treasure probability at which to dig.
+	pub wumpus_threshold   : f64, // The wumpus probability at which to shoot.
+	pub cost_offset        : i32, // Flattens the path cost of the heuristic exploration.
+	pub information_value  : f64, // The score worth of learning one bit about the treasures.
+	pub death_aversion     : f64, // How much more than the lost treasures a death is feared.
+}
+
+impl Default for ParamsBayes {
+	fn default() -> Self {
+		Self {
+			exploration        : Default::default(),
+			treasure_threshold : 0.25,
+			wumpus_threshold   : 1.0,
+			cost_offset        : 20,
+			information_value  : 5.0,
+			death_aversion     : 10.0,
+		}
+	}
+}
+
+impl Params for ParamsBayes {
+	fn validate(&self) -> Result<(), String> {
+
+		// The heuristic exploration divides by the offset path cost, which must stay positive.
+		if self.cost_offset <= 0 {
+			return Err("The cost offset must be positive".to_string());
+		}
+
+		Ok(())
+	}
+}
+
 #[derive(Default)]
 pub struct ModelBayes {
 	pub treasures_found: i32,
 	pub wumpuses_killed: i32,
 	pub blacklist: HashMap<Coordinate, Class>,
 	pub action_queue: std::collections::VecDeque<Action>,
+	pub params: ParamsBayes,
+	pub explanation: Option<Explanation>,
+	pub claims: HashMap<String, Coordinate>, // The location every teammate is heading for.
+	pub claim: Option<Coordinate>,           // The location of a new intent, to announce to the team.
+	pub teammate_actions: HashMap<String, u32>, // The actions every teammate had taken when last observed.
 }
 
 impl ModelBayes {
 
+	fn perform(&mut self, intent: Intent, beliefs: Vec<Belief>, candidates: Vec<Candidate>) -> Action {
+		let action = self.action_queue.pop_front().unwrap();
+		self.claim = Some(intent.location());
+		self.explanation = Some(Explanation {
+			beliefs,
+			candidates,
+			intent,
+			queue: self.action_queue.iter().copied().collect(),
+		});
+		return action;
+	}
+
 	fn naive_bayes_classifier(&self, c: f64, n: f64, i: f64, a: f64) -> f64{
 
 		/* Naive Bayes equation: https://en.wikipedia.org/wiki/Naive_Bayes_classifier
@@ -91,27 +364,112 @@ impl ModelBayes {
 		return posterior;
 	}
 
+	fn binary_entropy(&self, p: f64) -> f64 {
+		if p <= 0.0 || p >= 1.0 { return 0.0; }
+		return -(p * p.log2() + (1.0 - p) * (1.0 - p).log2());
+	}
+
+	fn information_gain(&self, location: &Coordinate, game: &Game, classes: &HashMap<Coordinate, ClassField<f64>>, treasure_prior: f64) -> f64 {
+
+		/* Visiting a location reveals whether any of its neighbours holds a treasure.
+		Since the glitter is fully determined by the treasure locations, the information
+		gained about the treasures equals the entropy of the glitter observation.
+		*/
+
+		let no_glitter: f64 = location
+			.get_neighbours()
+			.iter()
+			.filter(|&neighbour| game.map.encompass(neighbour))
+			.map(|neighbour| match classes.get(neighbour) {
+				Some(class) => class.treasure,
+				None if game.map.discovered.contains(neighbour) => 0.0,
+				None => treasure_prior,
+			})
+			.map(|treasure| 1.0 - treasure)
+			.product();
+
+		return self.binary_entropy(1.0 - no_glitter);
+	}
+
 }
 
 impl Model for ModelBayes {
-	fn run(&mut self, game: &Game) -> Action {
 
-		// Remember important events
+	fn new_game(&mut self) {
+		*self = Self{params: self.params.clone(), .. Default::default()};
+	}
+
+	fn observe(&mut self, _action: &Action, game: &Game) {
+
+		// Remember important events, including the treasures dug up by opponents since the last action.
 		if game.events.treasure { self.treasures_found += 1; }
 		if game.events.scream   { self.wumpuses_killed += 1; }
+		self.treasures_found += game.events.stolen as i32;
+	}
+
+	fn explain(&self) -> Option<Explanation> {
+		self.explanation.clone()
+	}
+
+	fn observe_team(&mut self, teammates: &[PlayerStatus], messages: &[TeamMessage]) {
+
+		// Count the treasures dug up by the team, once each, as the percepts of a teammate last until their next action.
+		for teammate in teammates.iter().filter(|teammate| teammate.active) {
+			let seen = self.teammate_actions.insert(teammate.name.clone(), teammate.actions) == Some(teammate.actions);
+			if teammate.events.treasure && ! seen {
+				self.treasures_found += 1;
+			}
+		}
+
+		// Leave the locations claimed by active teammates to them.
+		for message in messages {
+			if let Some(claim) = message.claim {
+				self.claims.insert(message.from.clone(), claim);
+			}
+		}
+		self.claims.retain(|name, _| teammates.iter().any(|teammate| teammate.name == *name && teammate.active));
+
+		// Give up the current intent if a teammate claimed it, or already dug the treasure.
+		if let Some(explanation) = &self.explanation {
+			let target = explanation.intent.location();
+			let dug = teammates.iter().any(|teammate| teammate.events.treasure && teammate.location == target);
+			if dug || self.claims.values().any(|&claim| claim == target) {
+				self.action_queue.clear();
+			}
+		}
+	}
+
+	fn team_message(&mut self) -> Option<TeamMessage> {
+		let location = self.claim.take()?;
+		let intent = match self.explanation.as_ref()?.intent {
+			Intent::Dig(_)     => "dig",
+			Intent::Shoot(_)   => "shoot",
+			Intent::Explore(_) => "explore",
+		};
+		Some(TeamMessage{from: String::new(), text: format!("Heading to {intent} {location}"), claim: Some(location)})
+	}
+
+	fn run(&mut self, game: &Game) -> Action {
+
+		// Teammates may have discovered the location to explore, or killed the wumpus to shoot.
+		let outdated = match self.explanation.as_ref().map(|explanation| explanation.intent) {
+			Some(Intent::Explore(location)) => game.map.discovered.contains(&location),
+			Some(Intent::Shoot(_)) => game.events.scream,
+			_ => false,
+		};
+		if outdated {
+			self.action_queue.clear();
+		}
 
 		// Finish performing the chosen abstract action
 		if ! self.action_queue.is_empty() {
-			return self.action_queue.pop_front().unwrap();
+			let action = self.action_queue.pop_front().unwrap();
+			if let Some(explanation) = &mut self.explanation {
+				explanation.queue = self.action_queue.iter().copied().collect();
+			}
+			return action;
 		}
 
-		// Calculate paths and cost to locations
-		let (path_map, path_costs) = algorithms::pathfind(
-			&game.location,
-			&game.direction,
-			&game.map
-		);
-
 		// Identify the locations which class is uncertain.
 		let frontier: Vec<Coordinate> = game.map.get_frontier().into_iter().collect();
 		let possible_treasures: Vec<Coordinate> = game.map.glitters
@@ -132,14 +490,15 @@ impl Model for ModelBayes {
 			&possible_treasures,
 			&game.map,
 			&self.blacklist,
+			&game.rules,
 		);
 
 		// Calculate general class statistics.
-		let map_size          : i32 = Game::SIZE_X * Game::SIZE_Y;
+		let map_size          : i32 = game.rules.size_x * game.rules.size_y;
 		let undiscovered_left : i32 = map_size - game.map.discovered.len() as i32;
-		let treasures_left    : i32 = Game::COUNT_TREASURES - game.map.treasures.len() as i32 - self.treasures_found;
-		let wumpuses_left     : i32 = Game::COUNT_WUMPUSES - game.map.wumpuses.len() as i32 - self.wumpuses_killed;
-		let pits_left         : i32 = Game::COUNT_PITS - game.map.pits.len() as i32;
+		let treasures_left    : i32 = game.rules.count_treasures - game.map.treasures.len() as i32 - self.treasures_found;
+		let wumpuses_left     : i32 = game.rules.count_wumpuses - game.map.wumpuses.len() as i32 - self.wumpuses_killed;
+		let pits_left         : i32 = game.rules.count_pits - game.map.pits.len() as i32;
 		let empties_left      : i32 = undiscovered_left - treasures_left - wumpuses_left - pits_left;
 
 		// Calculate class probabilities.
@@ -177,57 +536,228 @@ impl Model for ModelBayes {
 			});
 		}
 
+		// Calculate the cheapest path to each location of interest.
+		let paths: HashMap<Coordinate, (i32, Vec<Action>)> = classes
+			.keys()
+			.filter_map(|location| algorithms::pathfind(
+				&game.location,
+				&game.direction,
+				location,
+				&game.rules,
+				algorithms::risk_cost(&game.map, &game.rules, &classes, location),
+			).map(|path| (*location, path)))
+			.collect()
+			;
+
+		// Remember the beliefs and considered locations to explain the decision.
+		let beliefs: Vec<Belief> = classes
+			.iter()
+			.map(|(&location, &probabilities)| Belief{location, probabilities})
+			.sorted_by_key(|belief| belief.location)
+			.collect()
+			;
+		let rank = |locations: Vec<(&Coordinate, f64)>| -> Vec<Candidate> {
+			locations
+				.into_iter()
+				.map(|(&location, score)| Candidate{location, score})
+				.sorted_by(|c1, c2| c2.score.total_cmp(&c1.score).then(c1.location.cmp(&c2.location)))
+				.collect()
+		};
+
+		/* The candidates come from a HashMap, which iterates in a different order in every run, so break
+		ties by location. Otherwise equally scored candidates would be picked at random, and neither games
+		nor tournaments could be reproduced. */
+		let best = |locations: &[(&Coordinate, f64)]| -> Option<Coordinate> {
+			locations
+				.iter()
+				.max_by(|(l1, s1), (l2, s2)| s1.total_cmp(s2).then(l2.cmp(l1)))
+				.map(|&(&location, _)| location)
+		};
+
+		// Leave the locations claimed by teammates to them.
+		let claimed = |location: &Coordinate| self.claims.values().any(|claim| claim == location);
+
 		// If treasure is known, dig it up
-		if let Some(treasure) = classes
+		let treasures: Vec<(&Coordinate, f64)> = classes
 			.iter()
-			.filter(|&(_, c)| c.treasure >= 0.25)
-			.min_by_key(|&(l, _)| path_costs[l])
-		{
-			let treasure = treasure.0;
-			self.blacklist.insert(*treasure, Class::Treasure);
-			let actions = algorithms::path_to_actions(&treasure, &game.direction, &path_map).unwrap();
-			self.action_queue.extend(actions);
+			.filter(|&(l, c)| c.treasure >= self.params.treasure_threshold && paths.contains_key(l) && ! claimed(l))
+			.map(|(l, _)| (l, -paths[l].0 as f64))
+			.collect()
+			;
+		if let Some(treasure) = best(&treasures) {
+			self.blacklist.insert(treasure, Class::Treasure);
+			self.action_queue.extend(&paths[&treasure].1);
 			self.action_queue.push_back(Action::Dig);
-			return self.action_queue.pop_front().unwrap();
+			return self.perform(Intent::Dig(treasure), beliefs, rank(treasures));
 		}
 
 		// If wumpus is known, shoot it
-		if let Some(wumpus) = classes
+		let wumpuses: Vec<(&Coordinate, f64)> = classes
 			.iter()
-			.filter(|&(_, c)| c.wumpus == 1.0)
-			.min_by_key(|&(l, _)| path_costs[l])
-		{
-			let wumpus = wumpus.0;
-			self.blacklist.insert(*wumpus, Class::Wumpus);
-			let actions = algorithms::path_to_actions(&wumpus, &game.direction, &path_map).unwrap();
-			self.action_queue.extend(actions);
+			.filter(|&(l, c)| c.wumpus >= self.params.wumpus_threshold && paths.contains_key(l) && ! claimed(l))
+			.map(|(l, _)| (l, -paths[l].0 as f64))
+			.collect()
+			;
+		if let Some(wumpus) = best(&wumpuses) {
+			self.blacklist.insert(wumpus, Class::Wumpus);
+			self.action_queue.extend(&paths[&wumpus].1);
 			self.action_queue.pop_back();
 			self.action_queue.push_back(Action::Shoot);
-			return self.action_queue.pop_front().unwrap();
+			return self.perform(Intent::Shoot(wumpus), beliefs, rank(wumpuses));
 		}
 
 		// Discover the most rewarding location
+		let treasure_prior = treasures_left as f64 / undiscovered_left as f64;
+		let treasure_value = (game.rules.score_treasure + game.rules.score_dug) as f64;
 		let get_score = |location: &Coordinate| -> f64 {
-			let class = classes[&location];
-			let safety = 1.0 - if class.wumpus != 0.0 {0.9999} else {class.pit};
-			let cost = 1.0 / (path_costs[&location] + 20) as f64;
-			let score = cost * safety;
-			return score;
+			let class = classes[location];
+			match self.params.exploration {
+				Exploration::Heuristic => {
+					let safety = 1.0 - if class.wumpus != 0.0 {0.9999} else {class.pit};
+					let cost = 1.0 / (paths[location].0 + self.params.cost_offset) as f64;
+					return cost * safety;
+				},
+				Exploration::Information => {
+					let gain = self.information_gain(location, game, &classes, treasure_prior);
+					let death_loss = class.wumpus * treasures_left as f64 * treasure_value * self.params.death_aversion;
+					let pit_loss = class.pit * -game.rules.score_pit as f64;
+					return (1.0 - class.wumpus) * gain * self.params.information_value - death_loss - pit_loss - paths[location].0 as f64;
+				},
+			}
 		};
 
-		if let Some(location) = classes
-			.iter()
-			.filter(|&(l, _)| ! game.map.discovered.contains(&l) )
-			.map(|(l, _)| (l, get_score(&l)) )
-			.max_by(|(_, s1), (_, s2)| s1.partial_cmp(&s2).unwrap())
-		{
-			let location = location.0;
-			let actions = algorithms::path_to_actions(&location, &game.direction, &path_map).unwrap();
-			self.action_queue.extend(actions);
-			return self.action_queue.pop_front().unwrap();
+		let explorable = |unclaimed: bool| -> Vec<(&Coordinate, f64)> {
+			classes
+				.iter()
+				.filter(|&(l, _)| ! game.map.discovered.contains(l) && paths.contains_key(l) && ! (unclaimed && claimed(l)))
+				.map(|(l, _)| (l, get_score(l)) )
+				.collect()
+		};
+
+		// Only go for a location claimed by a teammate if there's nothing else left.
+		let mut locations = explorable(true);
+		if locations.is_empty() {
+			locations = explorable(false);
+		}
+		if let Some(location) = best(&locations) {
+			self.action_queue.extend(&paths[&location].1);
+			return self.perform(Intent::Explore(location), beliefs, rank(locations));
 		}
 
 		unreachable!();
 	}
 }
 
+
+// ---
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+	use std::cell::RefCell;
+	use std::rc::Rc;
+
+	use crate::wumpus::{Direction, Events, Map, Rules};
+
+	// What a model observed of its team, kept after the model is handed to play_shared.
+	#[derive(Default)]
+	struct Observed {
+		treasures_found : i32,
+		messages        : Vec<TeamMessage>,
+		dug             : HashSet<Coordinate>, // Where the model or its teammates dug up a treasure.
+	}
+
+	// Plays like the bayes model, checking that it never heads for a treasure which was already dug up.
+	struct Watched {
+		model    : ModelBayes,
+		observed : Rc<RefCell<Observed>>,
+	}
+
+	impl Model for Watched {
+
+		fn new_game(&mut self) {
+			self.model.new_game();
+		}
+
+		fn run(&mut self, game: &Game) -> Action {
+			let action = self.model.run(game);
+			let mut observed = self.observed.borrow_mut();
+			if let Some(Intent::Dig(location)) = self.model.explain().map(|explanation| explanation.intent) {
+				assert!(! observed.dug.contains(&location), "heading to dig at {location}, which was already dug up");
+			}
+			observed.treasures_found = self.model.treasures_found;
+			return action;
+		}
+
+		fn observe(&mut self, action: &Action, game: &Game) {
+			if game.events.treasure {
+				self.observed.borrow_mut().dug.insert(game.location);
+			}
+			self.model.observe(action, game);
+		}
+
+		fn observe_team(&mut self, teammates: &[PlayerStatus], messages: &[TeamMessage]) {
+			let mut observed = self.observed.borrow_mut();
+			observed.messages.extend_from_slice(messages);
+			observed.dug.extend(teammates.iter().filter(|teammate| teammate.events.treasure).map(|teammate| teammate.location));
+			self.model.observe_team(teammates, messages);
+		}
+
+		fn team_message(&mut self) -> Option<TeamMessage> {
+			self.model.team_message()
+		}
+
+	}
+
+	#[test]
+	fn counts_every_treasure_of_the_team_once() {
+		let teammate = PlayerStatus {
+			name      : "teammate".to_string(),
+			location  : Coordinate{x: 1, y: 0},
+			direction : Direction::East,
+			score     : 0,
+			active    : true,
+			events    : Events{treasure: true, .. Default::default()},
+			actions   : 3,
+		};
+		let mut model = ModelBayes::default();
+
+		// Percepts last until the next action of the teammate, so they may be observed again.
+		model.observe_team(std::slice::from_ref(&teammate), &[]);
+		model.observe_team(std::slice::from_ref(&teammate), &[]);
+		assert_eq!(model.treasures_found, 1);
+		model.observe_team(&[PlayerStatus{actions: 4, events: Events::default(), .. teammate.clone()}], &[]);
+		model.observe_team(&[PlayerStatus{actions: 5, .. teammate}], &[]);
+		assert_eq!(model.treasures_found, 2);
+	}
+
+	#[test]
+	fn plays_as_a_team() {
+		let map: Map = ". . . T\nT . . .\nS . . T".parse().unwrap();
+		let game = Game::from_map(&Rules::default().with_map(&map), map);
+		let names = vec!["alice".to_string(), "bob".to_string()];
+		let observed: Vec<Rc<RefCell<Observed>>> = names.iter().map(|_| Default::default()).collect();
+		let mut models: Vec<Box<dyn Model>> = observed
+			.iter()
+			.map(|observed| Box::new(Watched{model: ModelBayes::default(), observed: observed.clone()}) as Box<dyn Model>)
+			.collect();
+
+		let game = play_shared(&mut models, SharedGame::new(&game, names.clone(), true), 500);
+		assert!(game.game_over && game.won(0) && game.won(1));
+		assert_eq!(game.explorers[0].score, game.explorers[1].score, "the team shares its score");
+		assert!(game.explorers.iter().all(|explorer| explorer.actions > 0), "both players took part");
+
+		// Every treasure but the last, found by the final action, was counted once by both players.
+		for (index, observed) in observed.iter().enumerate() {
+			let observed = observed.borrow();
+			assert_eq!(observed.treasures_found, game.rules.count_treasures - 1);
+
+			// The claims of the teammate were relayed, under their name.
+			let teammate = &names[1 - index];
+			assert!(! observed.messages.is_empty());
+			assert!(observed.messages.iter().all(|message| message.from == *teammate && message.claim.is_some()));
+		}
+	}
+
+}
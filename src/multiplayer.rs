@@ -0,0 +1,382 @@
+
+use std::collections::HashSet;
+
+use serde::{Serialize, Deserialize};
+
+use crate::wumpus::{
+	Action,
+	Coordinate,
+	Direction,
+	Events,
+	Game,
+	Map,
+	Rules,
+};
+
+/* Several players exploring the same cave, taking turns. The cave is shared, so a treasure dug up or
+a wumpus shot by one player is gone for everyone, while every player has their own location and
+arrows. Competing players also have their own score and discovered tiles, and their arrows hit other
+players as well as wumpuses. Cooperating players form a team instead, sharing the discovered tiles,
+their percepts and a single score.
+
+Every player sees the cave as a regular Game through view, so the models can play without knowing
+about the other players, and the rules of a single player game apply to every action. */
+
+// A player in a shared cave.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Explorer {
+	pub name       : String,
+	pub location   : Coordinate,
+	pub direction  : Direction,
+	pub events     : Events, // The events since the last action of the player.
+	pub score      : i32,
+	pub arrows     : i32,
+	pub active     : bool,   // False once the player has been eliminated or has left.
+	pub discovered : HashSet<Coordinate>,
+	#[serde(default)]
+	pub actions    : u32,
+}
+
+// What every player can see of a player.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlayerStatus {
+	pub name      : String,
+	pub location  : Coordinate,
+	pub direction : Direction,
+	pub score     : i32,
+	pub active    : bool,
+	#[serde(default)]
+	pub events    : Events, // The percepts of the last action, only shared with teammates.
+	#[serde(default)]
+	pub actions   : u32,    // The actions taken so far, telling the percepts of a new action from those already seen.
+}
+
+// A message between teammates, relayed by the server.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TeamMessage {
+	#[serde(default)]
+	pub from  : String,             // The name of the sender, filled in by the server.
+	pub text  : String,
+	#[serde(default)]
+	pub claim : Option<Coordinate>, // The location the sender is heading for, left to them by the others.
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SharedGame {
+	pub map       : Map,            // The discovered tiles are those discovered by any player.
+	pub explorers : Vec<Explorer>,
+	pub turn      : usize,          // The index of the player to act next.
+	pub game_over : bool,
+	pub rules     : Rules,
+	#[serde(default)]
+	pub team      : bool,           // Whether the players cooperate instead of competing.
+}
+
+impl SharedGame {
+
+	// Places every player at the spawn location of a new game.
+	pub fn new(game: &Game, names: Vec<String>, team: bool) -> Self {
+		let explorers = names
+			.into_iter()
+			.map(|name| Explorer {
+				name,
+				location   : game.location,
+				direction  : game.direction,
+				events     : game.events,
+				score      : game.score,
+				arrows     : game.arrows,
+				active     : ! game.game_over,
+				discovered : game.map.discovered.clone(),
+				actions    : 0,
+			})
+			.collect();
+		Self {
+			map       : game.map.clone(),
+			explorers,
+			turn      : 0,
+			game_over : game.game_over,
+			rules     : game.rules.clone(),
+			team,
+		}
+	}
+
+
+	// The game as seen by a player, with the full map besides the tiles they haven't discovered.
+	pub fn view(&self, index: usize) -> Game {
+		let explorer = &self.explorers[index];
+		let mut map = self.map.clone();
+		if ! self.team {
+			map.discovered = explorer.discovered.clone();
+		}
+		Game {
+			map,
+			location  : explorer.location,
+			direction : explorer.direction,
+			events    : explorer.events,
+			game_over : self.game_over || ! explorer.active,
+			score     : explorer.score,
+			arrows    : explorer.arrows,
+			rules     : self.rules.clone(),
+		}
+	}
+
+
+	// Where the other players are, as seen by a player.
+	pub fn others(&self, index: usize) -> Vec<PlayerStatus> {
+		self.standings()
+			.into_iter()
+			.enumerate()
+			.filter(|(other, _)| *other != index)
+			.map(|(_, player)| player)
+			.collect()
+	}
+
+
+	// Every player, in the order of the turns.
+	pub fn standings(&self) -> Vec<PlayerStatus> {
+		self.explorers
+			.iter()
+			.map(|explorer| PlayerStatus {
+				name      : explorer.name.clone(),
+				location  : explorer.location,
+				direction : explorer.direction,
+				score     : explorer.score,
+				active    : explorer.active,
+				events    : if self.team { explorer.events } else { Events::default() },
+				actions   : explorer.actions,
+			})
+			.collect()
+	}
+
+
+	// Whether the team found every treasure, or the player has the best score once the game is over.
+	pub fn won(&self, index: usize) -> bool {
+		if self.team {
+			return self.game_over && self.map.treasures.is_empty();
+		}
+		let best = self.explorers.iter().map(|explorer| explorer.score).max();
+		self.game_over && best == Some(self.explorers[index].score)
+	}
+
+
+	// Performs the action of the player whose turn it is, returning the other players hit by an arrow.
+	pub fn do_action(&mut self, action: Action) -> Vec<usize> {
+
+		let index = self.turn;
+		let mut game = self.view(index);
+		let arrows = game.arrows;
+		game.do_action(action);
+
+		// Arrows fly into the tile in front of the player, where opponents may stand.
+		let mut hit = Vec::new();
+		if game.arrows < arrows && ! self.team {
+			let target = game.location.get_front(&game.direction);
+			for (other, explorer) in self.explorers.iter_mut().enumerate() {
+				if other != index && explorer.active && explorer.location == target {
+					explorer.active = false;
+					explorer.events.shot = true;
+					explorer.score += self.rules.score_wumpus;
+					hit.push(other);
+				}
+			}
+		}
+
+		// The scream of a wumpus echoes throughout the cave.
+		if game.events.scream {
+			self.explorers.iter_mut().for_each(|explorer| explorer.events.scream = true);
+		}
+
+		// Opponents hear a treasure being dug up, while teammates see it in the percepts of the team.
+		if game.events.treasure && ! self.team {
+			for (other, explorer) in self.explorers.iter_mut().enumerate() {
+				if other != index { explorer.events.stolen += 1; }
+			}
+		}
+
+		// A team shares its score.
+		if self.team {
+			let change = game.score - self.explorers[index].score;
+			self.explorers.iter_mut().for_each(|explorer| explorer.score += change);
+		}
+
+		// Share the changes to the cave, and keep the rest for the player.
+		let explorer = &mut self.explorers[index];
+		explorer.discovered = std::mem::take(&mut game.map.discovered);
+		let mut discovered = std::mem::take(&mut self.map.discovered);
+		discovered.extend(explorer.discovered.iter().copied());
+		self.map = game.map;
+		self.map.discovered = discovered;
+		explorer.location = game.location;
+		explorer.direction = game.direction;
+		explorer.events = game.events;
+		explorer.score = game.score;
+		explorer.arrows = game.arrows;
+		explorer.active = ! game.events.wumpus;
+		explorer.actions += 1;
+
+		// The game is over once all treasures have been found, or no one is left to find them.
+		if self.map.treasures.is_empty() || ! self.explorers.iter().any(|explorer| explorer.active) {
+			self.game_over = true;
+		}
+		else {
+			self.next_turn();
+		}
+		return hit;
+	}
+
+
+	// Removes a player from the game, passing the turn on if it was theirs.
+	pub fn leave(&mut self, index: usize) {
+		self.explorers[index].active = false;
+		self.explorers[index].events = Events::default();
+		if ! self.explorers.iter().any(|explorer| explorer.active) {
+			self.game_over = true;
+		}
+		else if self.turn == index {
+			self.next_turn();
+		}
+	}
+
+
+	fn next_turn(&mut self) {
+		let count = self.explorers.len();
+		for offset in 1..=count {
+			let index = (self.turn + offset) % count;
+			if self.explorers[index].active {
+				self.turn = index;
+				return;
+			}
+		}
+	}
+
+}
+
+// ---
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	// A cave with the map, shared by players named after their index.
+	fn shared(map: &str, players: usize, team: bool) -> SharedGame {
+		let map: Map = map.parse().unwrap();
+		let game = Game::from_map(&Rules::default().with_map(&map), map);
+		return SharedGame::new(&game, (0..players).map(|index| index.to_string()).collect(), team);
+	}
+
+	#[test]
+	fn arrows_eliminate_opponents() {
+		let mut game = shared("S . . T", 2, false);
+		let rules = game.rules.clone();
+		assert!(game.do_action(Action::Walk).is_empty());
+		assert_eq!(game.turn, 1);
+
+		// The second player shoots the first, who stands in front of them, and keeps the turn.
+		assert_eq!(game.do_action(Action::Shoot), [0]);
+		let shot = &game.explorers[0];
+		assert!(! shot.active && shot.events.shot);
+		assert_eq!(shot.score, rules.score_action + rules.score_wumpus);
+		assert!(game.view(0).game_over);
+		assert_eq!(game.turn, 1);
+		assert!(! game.game_over);
+
+		// Teammates can't hit each other.
+		let mut team = shared("S . . T", 2, true);
+		team.do_action(Action::Walk);
+		assert!(team.do_action(Action::Shoot).is_empty());
+		assert!(team.explorers[0].active);
+	}
+
+	#[test]
+	fn opponents_hear_treasures_being_dug_up() {
+		let mut game = shared("S T . T", 2, false);
+		game.do_action(Action::Walk);
+		game.do_action(Action::Left);
+		game.do_action(Action::Dig);
+		assert!(game.explorers[0].events.treasure);
+		assert_eq!(game.explorers[0].events.stolen, 0);
+		assert_eq!(game.explorers[1].events.stolen, 1);
+		assert_eq!(game.view(1).events.stolen, 1);
+		assert_eq!(game.map.treasures.len(), 1, "the treasure is gone for everyone");
+
+		// The player only hears it until their next action.
+		game.do_action(Action::Right);
+		assert_eq!(game.explorers[1].events.stolen, 0);
+
+		// Teammates see the treasure in the percepts of the team instead.
+		let mut team = shared("S T . T", 2, true);
+		team.do_action(Action::Walk);
+		team.do_action(Action::Left);
+		team.do_action(Action::Dig);
+		assert_eq!(team.explorers[1].events.stolen, 0);
+		assert!(team.others(1)[0].events.treasure);
+	}
+
+	#[test]
+	fn skips_eliminated_and_departed_players() {
+		let mut game = shared("S . . T T", 3, false);
+		game.do_action(Action::Walk);
+		assert_eq!(game.turn, 1);
+
+		// Players leaving out of turn keep the turn where it is, and those leaving on their turn pass it on.
+		game.leave(0);
+		assert_eq!(game.turn, 1);
+		game.leave(1);
+		assert_eq!(game.turn, 2);
+		assert!(! game.game_over);
+
+		// The last player left takes every turn, until they leave as well.
+		game.do_action(Action::Left);
+		assert_eq!(game.turn, 2);
+		game.leave(2);
+		assert!(game.game_over);
+		assert!(game.explorers.iter().all(|explorer| ! explorer.active));
+
+		// Eliminated players are skipped as well.
+		let mut game = shared("S . . T T", 3, false);
+		game.do_action(Action::Walk);
+		game.do_action(Action::Left);
+		assert_eq!(game.do_action(Action::Shoot), [0]);
+		assert_eq!(game.turn, 1);
+		game.do_action(Action::Right);
+		assert_eq!(game.turn, 2);
+	}
+
+	#[test]
+	fn ranks_the_players_once_the_game_is_over() {
+		let mut game = shared("S T", 2, false);
+		let rules = game.rules.clone();
+		game.do_action(Action::Walk);
+		assert!(! game.won(0) && ! game.won(1), "no one wins before the game is over");
+		game.do_action(Action::Right);
+		game.do_action(Action::Dig);
+		assert!(game.game_over);
+		assert!(game.won(0) && ! game.won(1));
+
+		let standings = game.standings();
+		assert_eq!(standings.iter().map(|player| player.name.as_str()).collect::<Vec<_>>(), ["0", "1"]);
+		assert_eq!(standings[0].score, 2 * rules.score_action + rules.score_dug + rules.score_treasure);
+		assert_eq!(standings[1].score, rules.score_action);
+		assert!(! standings[0].events.treasure, "opponents don't share their percepts");
+
+		// A team shares its score, and wins together by finding every treasure.
+		let mut team = shared("S T", 2, true);
+		team.do_action(Action::Walk);
+		team.do_action(Action::Right);
+		team.do_action(Action::Dig);
+		let score = 3 * rules.score_action + rules.score_dug + rules.score_treasure;
+		assert!(team.standings().iter().all(|player| player.score == score));
+		assert!(team.won(0) && team.won(1));
+		assert!(team.standings()[0].events.treasure);
+
+		// But loses together if no one is left to find them.
+		let mut team = shared("S W T", 2, true);
+		team.do_action(Action::Walk);
+		assert!(! team.explorers[0].active);
+		team.leave(1);
+		assert!(team.game_over);
+		assert!(! team.won(0) && ! team.won(1));
+	}
+
+}
@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+/* A thin wrapper around poll(2), letting a single thread wait for any number of sockets to become
+readable or writable. The standard library doesn't expose it, and it's part of every libc.
+
+Other platforms, such as Windows, get a fallback which sleeps briefly and reports every socket as
+ready for what it's waited on. Since all sockets are non-blocking, this is correct, but it wakes up
+the server every few milliseconds even while idle. */
+
+pub const READABLE : i16 = 0x001; // POLLIN
+pub const WRITABLE : i16 = 0x004; // POLLOUT
+pub const ERROR    : i16 = 0x008; // POLLERR
+pub const HANGUP   : i16 = 0x010; // POLLHUP
+
+#[cfg(unix)]
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct PollFd {
+	fd          : std::os::fd::RawFd,
+	events      : i16,
+	pub revents : i16,
+}
+
+#[cfg(not(unix))]
+#[derive(Clone, Copy, Debug)]
+pub struct PollFd {
+	events      : i16,
+	pub revents : i16,
+}
+
+impl PollFd {
+
+	#[cfg(unix)]
+	pub fn new(source: &impl std::os::fd::AsRawFd, events: i16) -> Self {
+		Self {
+			fd      : source.as_raw_fd(),
+			events,
+			revents : 0,
+		}
+	}
+
+	#[cfg(not(unix))]
+	pub fn new<T>(_source: &T, events: i16) -> Self {
+		Self {
+			events,
+			revents : 0,
+		}
+	}
+
+	pub fn readable(&self) -> bool {
+		self.revents & (READABLE | ERROR | HANGUP) != 0
+	}
+
+	pub fn writable(&self) -> bool {
+		self.revents & (WRITABLE | ERROR | HANGUP) != 0
+	}
+
+}
+
+// The type of the descriptor count, `nfds_t`, which differs between the libcs.
+#[cfg(all(unix, any(target_os = "linux", target_os = "android")))]
+type Nfds = std::ffi::c_ulong;
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+type Nfds = std::ffi::c_uint;
+
+#[cfg(unix)]
+extern "C" {
+	#[link_name = "poll"]
+	fn poll_ffi(fds: *mut PollFd, nfds: Nfds, timeout: std::ffi::c_int) -> std::ffi::c_int;
+}
+
+// Waits until any of the file descriptors are ready or the timeout passed, returning how many are ready.
+#[cfg(unix)]
+pub fn poll(fds: &mut [PollFd], timeout: Duration) -> std::io::Result<usize> {
+	let timeout = timeout.as_millis().min(i32::MAX as u128) as std::ffi::c_int;
+
+	// Safe, as the pointer and length describe a valid slice of `struct pollfd`, which poll only writes `revents` of.
+	let ready = unsafe { poll_ffi(fds.as_mut_ptr(), fds.len() as Nfds, timeout) };
+	if ready < 0 {
+		let error = std::io::Error::last_os_error();
+		if error.kind() == std::io::ErrorKind::Interrupted {
+			return Ok(0);
+		}
+		return Err(error);
+	}
+	return Ok(ready as usize);
+}
+
+#[cfg(not(unix))]
+pub fn poll(fds: &mut [PollFd], timeout: Duration) -> std::io::Result<usize> {
+	const INTERVAL: Duration = Duration::from_millis(5);
+
+	std::thread::sleep(timeout.min(INTERVAL));
+	for fd in fds.iter_mut() {
+		fd.revents = fd.events;
+	}
+	return Ok(fds.iter().filter(|fd| fd.revents != 0).count());
+}
@@ -0,0 +1,243 @@
+
+use serde::{Serialize, Deserialize};
+
+use crate::wumpus::{
+	Action,
+	Game,
+	Map,
+	Rules,
+};
+use crate::leaderboard::Entry;
+use crate::replay::Replay;
+use crate::multiplayer::{PlayerStatus, TeamMessage};
+
+/* The client and server exchange JSON objects over TCP, one after another.
+Every message is an object with a "type" field naming the message, e.g:
+
+	{"type":"Hello","version":1,"client_name":"my-bot","player":"alice","model":"bayes","seed":null}
+
+1. The client sends Hello.
+2. The server replies with Welcome, or with Error and disconnects if the version is unsupported.
+3. The server sends State, to which the client replies with Action. This repeats until the game is over.
+4. The server sends the final State followed by GameOver, and disconnects.
+
+At any point, the client may send LeaderboardQuery, to which the server replies with Leaderboard.
+
+Clients greeting with daily set play the daily challenge, the same map for every player on the same day
+in UTC. Their scores are ranked apart from other games, and queried by setting daily in LeaderboardQuery.
+Its seed is derived from a secret of the server and kept secret until the day ends, so greeting with
+it explicitly, with another seed picking the same map of the server, or with daily set in a match, is
+answered with Error.
+
+In a competitive match, several clients greeting with the same mode share a cave and take turns. The
+server sends Lobby while waiting for enough players, and afterwards only sends State to the player
+whose turn it is. Players which are eliminated receive GameOver right away, the others once the
+match is over. A cooperative match works the same way, except that the players form a team sharing
+the discovered tiles and the score. Players of a match may send Chat at any point, which the server
+relays to the other players.
+The scores of matches are ranked apart from single player games, by mode, and queried by setting mode
+in LeaderboardQuery.
+
+Instead of Hello, a client may send Spectate to watch the session of another player. The server then
+sends the fully revealed State after every action of the player, followed by GameOver once the game is
+over, or an Error if the session doesn't exist or the player leaves. Spectators of the daily challenge
+only receive the final State and GameOver. Other games which are spectated before they're over no longer
+enter the leaderboard, since the player could learn the map from a spectator. Matches can't be spectated
+at all, so the session of a player in one is answered with Error as well.
+
+Bad messages are answered with Error, after which the server sends the State again. JSON which can't
+be parsed is skipped up to the next line or object, and counts as a bad message as well. Clients
+sending too many bad messages are disconnected.
+*/
+
+pub const VERSION: u32 = 1;
+
+// ---
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Mode {
+	#[default]
+	Single,      // A cave of its own for every player.
+	Competitive, // Several players racing for the treasures in the same cave.
+	Cooperative, // Several players searching the same cave as a team.
+}
+
+impl std::str::FromStr for Mode {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_lowercase().as_str() {
+			"single" => Ok(Self::Single),
+			"competitive" => Ok(Self::Competitive),
+			"cooperative" => Ok(Self::Cooperative),
+			_ => Err(format!("Unknown mode: {s}")),
+		}
+	}
+}
+
+// ---
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ErrorCode {
+	VersionMismatch,   // The client speaks another protocol version.
+	UnexpectedMessage, // The message isn't valid at this point of the session.
+	InvalidMessage,    // The message is valid JSON, but not a known message.
+	MalformedMessage,  // The message isn't valid JSON, and was skipped up to the next line or object.
+	TooManyErrors,     // The client has sent too many bad messages.
+	ServerFull,        // The server has reached its maximum number of clients.
+	Timeout,           // The client has been idle for too long.
+	NoSuchSession,     // The session to spectate doesn't exist, or plays in a match, which can't be spectated.
+	SessionEnded,      // The player of the spectated session left before the game was over.
+}
+
+impl ErrorCode {
+
+	// Whether the server disconnects after sending this error.
+	pub fn is_fatal(&self) -> bool {
+		!matches!(self, Self::UnexpectedMessage | Self::InvalidMessage | Self::MalformedMessage)
+	}
+
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GameResult {
+	pub score     : i32,
+	pub won       : bool,                // All treasures were found, or the best score of a match.
+	pub map       : Map,                 // The fully revealed map.
+	#[serde(default)]
+	pub replay    : Option<Box<Replay>>, // Every action of the game, to play it again.
+	#[serde(default)]
+	pub standings : Vec<PlayerStatus>,   // The scores of every player of a match.
+}
+
+impl GameResult {
+
+	pub fn new(game: &Game, replay: &Replay) -> Self {
+		Self {
+			score     : game.score,
+			won       : game.map.treasures.is_empty(),
+			map       : game.map.clone(),
+			replay    : Some(Box::new(replay.clone())),
+			standings : Vec::new(),
+		}
+	}
+
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Message {
+
+	// Sent by the client.
+	Hello {
+		version     : u32,
+		client_name : String,
+		#[serde(default)]
+		player      : Option<String>, // The name shown on the leaderboard.
+		model       : String,
+		seed        : Option<u64>,    // Generate the map from this seed, or randomly if none is given.
+		#[serde(default)]
+		mode        : Mode,
+		#[serde(default)]
+		daily       : bool,           // Play the daily challenge, ignoring the seed.
+	},
+	Action {
+		action : Action,
+	},
+	Chat {
+		message : TeamMessage,
+	},
+	LeaderboardQuery {
+		count : usize,
+		#[serde(default)]
+		rules : Option<Rules>, // Query the scores of these rules instead of those the server plays with.
+		#[serde(default)]
+		daily : bool,          // Query the scores of the daily challenge of today instead.
+		#[serde(default)]
+		mode  : Mode,          // Query the scores of matches of this mode instead of single player games.
+	},
+	Spectate {
+		version    : u32,
+		session_id : u64,
+	},
+
+	// Sent by the server.
+	Welcome {
+		session_id : u64,
+		rules      : Rules, // The rules of the game, or of the match, which the map of the server may change.
+	},
+	State {
+		game   : Game,              // The game, with all undiscovered information hidden, unless spectating.
+		#[serde(default)]
+		others : Vec<PlayerStatus>, // The other players sharing the cave.
+	},
+	GameOver {
+		result : GameResult,
+	},
+	Leaderboard {
+		entries : Vec<Entry>, // Sorted from the highest score.
+	},
+	Lobby {
+		players : Vec<String>, // The players waiting for the match, which starts once there are enough.
+		needed  : usize,
+	},
+	Error {
+		code    : ErrorCode,
+		message : String,
+	},
+}
+
+impl Message {
+
+	pub fn hello(client_name: &str, player: Option<&str>, model: &str, seed: Option<u64>, mode: Mode, daily: bool) -> Self {
+		Self::Hello {
+			version     : VERSION,
+			client_name : client_name.to_string(),
+			player      : player.map(str::to_string),
+			model       : model.to_string(),
+			seed,
+			mode,
+			daily,
+		}
+	}
+
+	pub fn error(code: ErrorCode, message: &str) -> Self {
+		Self::Error {
+			code,
+			message : message.to_string(),
+		}
+	}
+
+}
+
+// ---
+
+/* The HTTP API keeps every game in a session table on the server, identified by its id:
+
+	POST /games                 Start a game, with a NewGameRequest body, responds with a GameResponse
+	GET  /games/{id}            Get the state of a game as a GameResponse
+	POST /games/{id}/actions    Perform an action, with an ActionRequest body, responds with a GameResponse
+	GET  /highscores?count=N    Get the best N entries of the leaderboard, for the rules the server plays with
+	GET  /daily?count=N         Get the date and best N entries of the daily challenge of today
+*/
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NewGameRequest {
+	pub player : Option<String>, // The name shown on the leaderboard.
+	pub model  : Option<String>, // The model playing, shown on the leaderboard.
+	pub seed   : Option<u64>,    // Generate the map from this seed, or randomly if none is given.
+	pub rules  : Option<Rules>,  // Play with these rules instead of those of the server.
+	pub daily  : bool,           // Play the daily challenge, ignoring the seed.
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ActionRequest {
+	pub action : Action,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GameResponse {
+	pub id     : u64,
+	pub game   : Game,               // The game, with all undiscovered information hidden.
+	pub result : Option<GameResult>, // The result, once the game is over.
+}
@@ -0,0 +1,356 @@
+
+use std::io::Write;
+
+use serde::{Serialize, Deserialize};
+
+use crate::multiplayer::SharedGame;
+use crate::wumpus::{
+	Action,
+	Events,
+	Game,
+	Map,
+	Rules,
+};
+
+/* A replay holds everything needed to play a game again: the rules, the seed or the initial map, and
+every action along with the events and score it resulted in. Playing the actions through do_action
+has to reproduce the same events and scores, which makes replays usable as regression tests. */
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Step {
+	pub action : Action,
+	pub events : Events, // The events caused by the action.
+	pub score  : i32,    // The score after the action.
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+	#[serde(default)]
+	pub player    : String,
+	#[serde(default)]
+	pub model     : String,
+	#[serde(default)]
+	pub timestamp : u64,         // Seconds since the Unix epoch, when the game started.
+	pub rules     : Rules,
+	pub seed      : Option<u64>, // Generate the map from this seed...
+	pub map       : Option<Map>, // ...or start from this map, when the seed doesn't generate it.
+	pub steps     : Vec<Step>,
+}
+
+impl Replay {
+
+	// Starts recording a game, which must not have been played yet.
+	pub fn new(game: &Game, seed: Option<u64>) -> Self {
+		Self {
+			player    : String::new(),
+			model     : String::new(),
+			timestamp : crate::leaderboard::Entry::now(),
+			rules     : game.rules.clone(),
+			seed,
+			map       : initial_map(game, seed),
+			steps     : Vec::new(),
+		}
+	}
+
+
+	// Performs the action in the game, and records it.
+	pub fn act(&mut self, game: &mut Game, action: Action) {
+		game.do_action(action);
+		self.steps.push(Step{action, events: game.events, score: game.score});
+	}
+
+
+	// The game as it was before the first action.
+	pub fn initial_game(&self) -> Result<Game, String> {
+		initial_game(&self.rules, self.seed, &self.map)
+	}
+
+
+	// Plays the game again, returning the state after every step, starting with the initial game.
+	pub fn play(&self) -> Result<Vec<Game>, String> {
+		let mut game = self.initial_game()?;
+		let mut games = vec![game.clone()];
+		for (index, step) in self.steps.iter().enumerate() {
+			if game.game_over {
+				return Err(format!("Step {} ({}) was played after the game was over", index + 1, step.action));
+			}
+			game.do_action(step.action);
+			if game.events != step.events || game.score != step.score {
+				return Err(format!("Step {} ({}) diverged: expected [{}] with a score of {}, but got [{}] with a score of {}",
+					index + 1,
+					step.action,
+					step.events,
+					step.score,
+					game.events,
+					game.score,
+				));
+			}
+			games.push(game.clone());
+		}
+		return Ok(games);
+	}
+
+
+	pub fn load(path: &str) -> Result<Self, String> {
+		let file = std::fs::File::open(path).map_err(|e| format!("Unable to open {path}: {e}"))?;
+		serde_json::from_reader(std::io::BufReader::new(file)).map_err(|e| format!("Invalid replay in {path}: {e}"))
+	}
+
+
+	pub fn save(&self, path: &str) -> Result<(), String> {
+		let write = || -> std::io::Result<()> {
+			let mut file = std::fs::File::create(path)?;
+			serde_json::to_writer_pretty(&mut file, self)?;
+			file.write_all(b"\n")
+		};
+		write().map_err(|e| format!("Unable to save the replay to {path}: {e}"))
+	}
+
+}
+
+// ---
+
+// Maps loaded from files are only picked by the seed, so keep them unless the seed generates them.
+fn initial_map(game: &Game, seed: Option<u64>) -> Option<Map> {
+	let generated = |seed: u64| {
+		let map = Game::new_seeded(&game.rules, seed).map;
+		map.treasures == game.map.treasures && map.wumpuses == game.map.wumpuses && map.pits == game.map.pits
+	};
+	(! seed.is_some_and(generated)).then(|| game.map.clone())
+}
+
+fn initial_game(rules: &Rules, seed: Option<u64>, map: &Option<Map>) -> Result<Game, String> {
+	rules.validate()?;
+	match (map, seed) {
+		(Some(map), _) => Ok(Game::from_map(rules, map.clone())),
+		(None, Some(seed)) => Ok(Game::new_seeded(rules, seed)),
+		(None, None) => Err("The replay has neither a seed nor a map".to_string()),
+	}
+}
+
+// ---
+
+/* The replay of a match, where the players take turns in the same cave. Every turn is either an action,
+or a player leaving the match, which passes the turn on as well. */
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Turn {
+	pub player : usize,          // The index of the player taking the turn.
+	pub action : Option<Action>, // None if the player left the match.
+	pub events : Events,         // The events caused by the action, for the player.
+	pub score  : i32,            // The score of the player after the action.
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MatchReplay {
+	pub players   : Vec<String>,
+	pub models    : Vec<String>,
+	pub team      : bool,
+	pub timestamp : u64,
+	pub rules     : Rules,
+	pub seed      : Option<u64>,
+	pub map       : Option<Map>,
+	pub turns     : Vec<Turn>,
+}
+
+impl MatchReplay {
+
+	// Starts recording a match, which must not have been played yet.
+	pub fn new(game: &Game, seed: Option<u64>, players: Vec<String>, models: Vec<String>, team: bool) -> Self {
+		Self {
+			players,
+			models,
+			team,
+			timestamp : crate::leaderboard::Entry::now(),
+			rules     : game.rules.clone(),
+			seed,
+			map       : initial_map(game, seed),
+			turns     : Vec::new(),
+		}
+	}
+
+
+	// Performs the action of the player whose turn it is, and records it. Returns the other players hit by an arrow.
+	pub fn act(&mut self, game: &mut SharedGame, action: Action) -> Vec<usize> {
+		let player = game.turn;
+		let hit = game.do_action(action);
+		let explorer = &game.explorers[player];
+		self.turns.push(Turn{player, action: Some(action), events: explorer.events, score: explorer.score});
+		return hit;
+	}
+
+
+	// Removes a player from the match, and records it.
+	pub fn leave(&mut self, game: &mut SharedGame, player: usize) {
+		game.leave(player);
+		self.turns.push(Turn{player, action: None, events: Events::default(), score: game.explorers[player].score});
+	}
+
+
+	// Whether the player left the match, rather than being eliminated or playing until the end.
+	pub fn left(&self, player: usize) -> bool {
+		self.turns.iter().any(|turn| turn.player == player && turn.action.is_none())
+	}
+
+
+	// The number of actions performed by the player.
+	pub fn actions(&self, player: usize) -> u32 {
+		self.turns.iter().filter(|turn| turn.player == player && turn.action.is_some()).count() as u32
+	}
+
+
+	// Plays the match again, returning the state after every turn, starting with the initial game.
+	pub fn play(&self) -> Result<Vec<SharedGame>, String> {
+		let initial_game = initial_game(&self.rules, self.seed, &self.map)?;
+		let mut game = SharedGame::new(&initial_game, self.players.clone(), self.team);
+		let mut games = vec![game.clone()];
+		for (index, turn) in self.turns.iter().enumerate() {
+			if turn.player >= game.explorers.len() {
+				return Err(format!("Turn {} was taken by the unknown player {}", index + 1, turn.player));
+			}
+			match turn.action {
+				_ if game.game_over => return Err(format!("Turn {} was taken after the match was over", index + 1)),
+				Some(action) if game.turn != turn.player => return Err(format!("Turn {} ({action}) was taken out of turn", index + 1)),
+				Some(action) => { game.do_action(action); },
+				None => game.leave(turn.player),
+			}
+			let explorer = &game.explorers[turn.player];
+			if turn.action.is_some() && (explorer.events != turn.events || explorer.score != turn.score) {
+				return Err(format!("Turn {} diverged: expected [{}] with a score of {}, but got [{}] with a score of {}",
+					index + 1,
+					turn.events,
+					turn.score,
+					explorer.events,
+					explorer.score,
+				));
+			}
+			games.push(game.clone());
+		}
+		return Ok(games);
+	}
+
+
+	pub fn load(path: &str) -> Result<Self, String> {
+		let file = std::fs::File::open(path).map_err(|e| format!("Unable to open {path}: {e}"))?;
+		serde_json::from_reader(std::io::BufReader::new(file)).map_err(|e| format!("Invalid replay in {path}: {e}"))
+	}
+
+
+	pub fn save(&self, path: &str) -> Result<(), String> {
+		let write = || -> std::io::Result<()> {
+			let mut file = std::fs::File::create(path)?;
+			serde_json::to_writer_pretty(&mut file, self)?;
+			file.write_all(b"\n")
+		};
+		write().map_err(|e| format!("Unable to save the replay to {path}: {e}"))
+	}
+
+}
+
+// ---
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+	use crate::algorithms;
+	use crate::models::{Model, ModelBayes};
+
+	// Records a game played by the bayes model.
+	fn record(mut game: Game, seed: Option<u64>) -> (Replay, Game) {
+		let mut replay = Replay::new(&game, seed);
+		let mut model = ModelBayes::default();
+		model.new_game();
+		while ! game.game_over && replay.steps.len() < 1000 {
+			let mut hidden_game = game.clone();
+			algorithms::hide_map(&mut hidden_game.map);
+			let action = model.run(&hidden_game);
+			replay.act(&mut game, action);
+			let mut hidden_game = game.clone();
+			algorithms::hide_map(&mut hidden_game.map);
+			model.observe(&action, &hidden_game);
+		}
+		return (replay, game);
+	}
+
+	// Plays the replay again after a round trip through JSON, checking it ends like the recorded game.
+	fn check_replay(replay: &Replay, recorded: &Game) {
+		let replay: Replay = serde_json::from_str(&serde_json::to_string(replay).unwrap()).unwrap();
+		let games = replay.play().unwrap();
+		assert_eq!(games.len(), replay.steps.len() + 1);
+		let last = games.last().unwrap();
+		assert_eq!(last.score, recorded.score);
+		assert_eq!(last.events, recorded.events);
+		assert_eq!(last.game_over, recorded.game_over);
+		assert_eq!(last.map.discovered, recorded.map.discovered);
+	}
+
+	#[test]
+	fn replays_seeded_games() {
+		for seed in 0..4 {
+			let (replay, game) = record(Game::new_seeded(&Default::default(), seed), Some(seed));
+			assert!(replay.map.is_none(), "the seed generates the map");
+			check_replay(&replay, &game);
+		}
+	}
+
+	#[test]
+	fn replays_full_maps() {
+		for seed in 0..4 {
+			let (replay, game) = record(Game::new_seeded(&Default::default(), seed), None);
+			assert!(replay.map.is_some(), "the map is kept without a seed");
+			check_replay(&replay, &game);
+		}
+	}
+
+	#[test]
+	fn detects_divergence() {
+		let (recorded, _) = record(Game::new_seeded(&Default::default(), 3), Some(3));
+
+		let mut replay = recorded.clone();
+		replay.steps[1].score += 1;
+		assert!(replay.play().unwrap_err().starts_with("Step 2 "));
+
+		let mut replay = recorded;
+		replay.steps.push(replay.steps[0].clone());
+		assert!(replay.play().is_err(), "steps after the game is over are rejected");
+	}
+
+	#[test]
+	fn replays_matches() {
+		for team in [false, true] {
+			let initial_game = Game::new_seeded(&Default::default(), 5);
+			let players = vec!["alice".to_string(), "bob".to_string()];
+			let mut game = SharedGame::new(&initial_game, players.clone(), team);
+			let mut replay = MatchReplay::new(&initial_game, Some(5), players, vec!["bayes".to_string(); 2], team);
+			let mut models = [ModelBayes::default(), ModelBayes::default()];
+			models.iter_mut().for_each(|model| model.new_game());
+			while ! game.game_over && replay.turns.len() < 1000 {
+				let player = game.turn;
+				let mut view = game.view(player);
+				algorithms::hide_map(&mut view.map);
+				let action = models[player].run(&view);
+				replay.act(&mut game, action);
+				let mut view = game.view(player);
+				algorithms::hide_map(&mut view.map);
+				models[player].observe(&action, &view);
+				if replay.turns.len() == 40 {
+					replay.leave(&mut game, 1);
+				}
+			}
+
+			let replay: MatchReplay = serde_json::from_str(&serde_json::to_string(&replay).unwrap()).unwrap();
+			let games = replay.play().unwrap();
+			assert_eq!(games.len(), replay.turns.len() + 1);
+			let last = games.last().unwrap();
+			for (explorer, recorded) in last.explorers.iter().zip(&game.explorers) {
+				assert_eq!(explorer.score, recorded.score);
+				assert_eq!(explorer.location, recorded.location);
+				assert_eq!(explorer.active, recorded.active);
+			}
+			assert_eq!(last.game_over, game.game_over);
+			assert_eq!(replay.left(1), replay.turns.len() > 40);
+		}
+	}
+
+}
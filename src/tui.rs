@@ -0,0 +1,247 @@
+
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+use std::collections::VecDeque;
+
+use crate::algorithms;
+use crate::wumpus::{
+	Action,
+	Game,
+};
+
+// ---
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+	Up,
+	Down,
+	Left,
+	Right,
+	Char(char),
+}
+
+fn read_keys(sender: mpsc::Sender<Key>) {
+	let mut bytes = std::io::stdin().lock().bytes().map_while(Result::ok).peekable();
+	while let Some(byte) = bytes.next() {
+		let key = match byte {
+
+			/* Arrow keys are sent as the escape sequences `ESC [ A` to `ESC [ D`. A lone Esc is ignored,
+			leaving the key pressed after it alone, and other sequences are skipped up to their final byte. */
+			0x1b if bytes.peek() == Some(&b'[') => {
+				bytes.next();
+				let Some(last) = bytes.find(|byte| (0x40..=0x7e).contains(byte)) else { break };
+				match last {
+					b'A' => Key::Up,
+					b'B' => Key::Down,
+					b'C' => Key::Right,
+					b'D' => Key::Left,
+					_ => continue,
+				}
+			},
+			0x1b => continue,
+
+			// In raw mode, Ctrl-C arrives as a byte instead of a signal.
+			0x03 => Key::Char('q'),
+
+			byte => Key::Char((byte as char).to_ascii_lowercase()),
+		};
+		if sender.send(key).is_err() { break; }
+	}
+}
+
+// ---
+
+pub struct Tui {
+	pub delay  : Duration,
+	pub paused : bool,
+	keys       : mpsc::Receiver<Key>,
+	log        : VecDeque<String>,
+	stty       : String,
+}
+
+impl Tui {
+
+	const LOG_LINES : usize    = 8;
+	const DELAY_MIN : Duration = Duration::from_millis(50);
+	const DELAY_MAX : Duration = Duration::from_secs(5);
+
+	pub fn new(delay: Duration) -> Result<Self, String> {
+
+		// Remember the terminal settings, so they can be restored afterwards.
+		let stty = Command::new("stty")
+			.arg("-g")
+			.stdin(Stdio::inherit())
+			.output()
+			.map_err(|e| format!("Unable to run stty: {e}"))?;
+		if ! stty.status.success() {
+			return Err("The terminal UI requires an interactive terminal".to_string());
+		}
+		let stty = String::from_utf8_lossy(&stty.stdout).trim().to_string();
+
+		// Read single key presses without echoing them.
+		Command::new("stty")
+			.args(["raw", "-echo"])
+			.stdin(Stdio::inherit())
+			.status()
+			.map_err(|e| format!("Unable to run stty: {e}"))?;
+
+		// Switch to the alternate screen and hide the cursor.
+		print!("\x1b[?1049h\x1b[?25l");
+		std::io::stdout().flush().map_err(|e| e.to_string())?;
+
+		let (sender, keys) = mpsc::channel();
+		std::thread::spawn(move || read_keys(sender));
+
+		Ok(Self {
+			delay,
+			paused : false,
+			keys,
+			log    : VecDeque::with_capacity(Self::LOG_LINES),
+			stty,
+		})
+	}
+
+
+	pub fn log(&mut self, message: &str) {
+		if self.log.len() == Self::LOG_LINES {
+			self.log.pop_front();
+		}
+		self.log.push_back(message.to_string());
+	}
+
+
+	pub fn draw(&self, game: &Game, manual: bool) {
+
+		let mut screen: Vec<String> = Vec::new();
+
+		// The grid panel.
+		screen.push("WumpusWorld".to_string());
+		screen.extend(algorithms::visualize_map(&game.map, &game.location, &game.direction, &false)
+			.lines()
+			.map(str::to_string)
+		);
+		screen.push(String::new());
+
+		// The status bar, highlighted in reverse video.
+		let mut status = format!(" Position: {} | Heading: {} | Arrows: {} | Score: {} ",
+			game.location,
+			game.direction,
+			game.arrows,
+			game.score,
+		);
+		if self.paused { status.push_str("| PAUSED "); }
+		screen.push(format!("\x1b[7m{status}\x1b[0m"));
+		screen.push(String::new());
+
+		// The event log.
+		screen.push("Events:".to_string());
+		screen.extend(self.log.iter().map(|message| format!("  {message}")));
+		screen.extend(std::iter::repeat_n(String::new(), Self::LOG_LINES - self.log.len()));
+		screen.push(String::new());
+		screen.push(if manual {
+			"Controls: [W/Up] walk, [A/Left] turn left, [D/Right] turn right, [S/Down] dig, [F] shoot, [Q] quit".to_string()
+		}
+		else {
+			format!("Controls: [Space] pause, [N] step, [+/-] speed ({}ms per action), [Q] quit", self.delay.as_millis())
+		});
+		self.render(&screen);
+	}
+
+
+	// Clears the terminal and draws the lines of the screen.
+	pub fn render(&self, screen: &[String]) {
+		// In raw mode, every line has to return the cursor to the start itself.
+		let mut stdout = std::io::stdout().lock();
+		write!(stdout, "\x1b[H\x1b[2J{}", screen.join("\r\n")).expect("Error while writing to stdout");
+		stdout.flush().expect("Error while flushing stdout");
+	}
+
+
+	// Waits for the next key press, or returns None once the input is closed.
+	pub fn read_key(&mut self) -> Option<Key> {
+		self.keys.recv().ok()
+	}
+
+
+	pub fn read_action(&mut self) -> Option<Action> {
+
+		/* Manual controls:
+		- Up/W: walk
+		- Left/A: turn left
+		- Right/D: turn right
+		- Down/S: dig
+		- F: shoot
+		- Q: quit
+		*/
+
+		loop {
+			match self.keys.recv().ok()? {
+				Key::Up    | Key::Char('w') => return Some(Action::Walk),
+				Key::Left  | Key::Char('a') => return Some(Action::Left),
+				Key::Right | Key::Char('d') => return Some(Action::Right),
+				Key::Down  | Key::Char('s') => return Some(Action::Dig),
+				Key::Char('f')              => return Some(Action::Shoot),
+				Key::Char('q')              => return None,
+				_                           => continue,
+			}
+		}
+	}
+
+
+	pub fn wait_for_step(&mut self, game: &Game) -> bool {
+
+		/* Bot controls, returning false when the player wants to quit:
+		- Space: pause or resume
+		- N: perform a single step while paused
+		- +/-: speed up or slow down
+		- Q: quit
+		*/
+
+		loop {
+			let key = if self.paused {
+				self.keys.recv().ok()
+			}
+			else {
+				match self.keys.recv_timeout(self.delay) {
+					Ok(key) => Some(key),
+					Err(mpsc::RecvTimeoutError::Timeout) => return true,
+					Err(mpsc::RecvTimeoutError::Disconnected) => None,
+				}
+			};
+
+			match key {
+				None                 => return false,
+				Some(Key::Char('q')) => return false,
+				Some(Key::Char(' ')) => { self.paused = ! self.paused; if ! self.paused { return true; } },
+				Some(Key::Char('n')) => if self.paused { return true; },
+				Some(Key::Char('+')) => self.delay = (self.delay / 2).max(Self::DELAY_MIN),
+				Some(Key::Char('-')) => self.delay = (self.delay * 2).min(Self::DELAY_MAX),
+				Some(_)              => continue,
+			}
+
+			// Show the changed pause state or speed.
+			self.draw(game, false);
+		}
+	}
+
+
+	pub fn wait_for_key(&mut self) {
+		let _ = self.keys.recv();
+	}
+
+}
+
+impl Drop for Tui {
+	fn drop(&mut self) {
+
+		// Leave the alternate screen, show the cursor and restore the terminal settings.
+		print!("\x1b[?25h\x1b[?1049l");
+		let _ = std::io::stdout().flush();
+		let _ = Command::new("stty")
+			.arg(&self.stty)
+			.stdin(Stdio::inherit())
+			.status();
+	}
+}
@@ -0,0 +1,502 @@
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+
+use crate::http;
+
+/* A minimal WebSocket implementation (RFC 6455), just enough to carry JSON messages between the
+server and browsers. Only text messages are supported, and binary messages are rejected. */
+
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+pub const OPCODE_CONTINUATION : u8 = 0x0;
+pub const OPCODE_TEXT         : u8 = 0x1;
+pub const OPCODE_CLOSE        : u8 = 0x8;
+pub const OPCODE_PING         : u8 = 0x9;
+pub const OPCODE_PONG         : u8 = 0xA;
+
+const MAX_MESSAGE: usize = 1 << 20;
+
+// ---
+
+// Accepts the upgrade request of a client, returning the response completing the handshake.
+pub fn accept(request: &http::Request) -> Result<http::Response, String> {
+	let upgrade = request.header("upgrade").unwrap_or_default();
+	if ! upgrade.eq_ignore_ascii_case("websocket") {
+		return Err("Expected a WebSocket upgrade request".to_string());
+	}
+	let key = request.header("sec-websocket-key").ok_or("Missing Sec-WebSocket-Key")?;
+	let response = http::Response{status: 101, headers: Vec::new(), body: Vec::new()};
+	return Ok(response
+		.with_header("Upgrade", "websocket")
+		.with_header("Connection", "Upgrade")
+		.with_header("Sec-WebSocket-Accept", &accept_key(key))
+	);
+}
+
+
+// Encodes a single frame. Clients have to mask the frames they send.
+pub fn encode_frame(opcode: u8, payload: &[u8], masked: bool) -> Vec<u8> {
+
+	let mut frame = vec![0x80 | opcode];
+	let mask_bit = if masked { 0x80 } else { 0x00 };
+	match payload.len() {
+		length @ 0..=125 => frame.push(mask_bit | length as u8),
+		length @ 126..=0xFFFF => {
+			frame.push(mask_bit | 126);
+			frame.extend((length as u16).to_be_bytes());
+		},
+		length => {
+			frame.push(mask_bit | 127);
+			frame.extend((length as u64).to_be_bytes());
+		},
+	}
+
+	if masked {
+		let mask: [u8; 4] = rand::random();
+		frame.extend(mask);
+		frame.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+	}
+	else {
+		frame.extend(payload);
+	}
+	return frame;
+}
+
+// ---
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Incoming {
+	Text(String),
+	Ping(Vec<u8>),
+	Close(Vec<u8>),
+}
+
+// Decodes the messages from the received bytes, reassembling fragmented messages.
+#[derive(Clone, Debug, Default)]
+pub struct Decoder {
+	buffer  : Vec<u8>,
+	message : Vec<u8>,
+}
+
+impl Decoder {
+
+	pub fn push(&mut self, bytes: &[u8]) {
+		self.buffer.extend_from_slice(bytes);
+	}
+
+
+	// Returns the next message, or None if it hasn't been received entirely yet.
+	pub fn decode(&mut self) -> Result<Option<Incoming>, String> {
+		loop {
+			let Some((fin, opcode, payload)) = self.next_frame()? else { return Ok(None) };
+			match opcode {
+				OPCODE_TEXT | OPCODE_CONTINUATION => {
+					if self.message.len() + payload.len() > MAX_MESSAGE {
+						return Err("The message is too large".to_string());
+					}
+					self.message.extend(payload);
+					if fin {
+						let message = std::mem::take(&mut self.message);
+						return String::from_utf8(message).map(|text| Some(Incoming::Text(text))).map_err(|_| "The message isn't valid UTF-8".to_string());
+					}
+				},
+				OPCODE_PING => return Ok(Some(Incoming::Ping(payload))),
+				OPCODE_PONG => (),
+				OPCODE_CLOSE => return Ok(Some(Incoming::Close(payload))),
+				_ => return Err("Only text messages are supported".to_string()),
+			}
+		}
+	}
+
+
+	fn next_frame(&mut self) -> Result<Option<(bool, u8, Vec<u8>)>, String> {
+
+		let buffer = &self.buffer;
+		if buffer.len() < 2 {
+			return Ok(None);
+		}
+		let fin = buffer[0] & 0x80 != 0;
+		let opcode = buffer[0] & 0x0F;
+		let masked = buffer[1] & 0x80 != 0;
+
+		// The payload length is either given directly, or in the following 2 or 8 bytes.
+		let (length, mut offset) = match buffer[1] & 0x7F {
+			126 if buffer.len() >= 4 => (u16::from_be_bytes([buffer[2], buffer[3]]) as u64, 4),
+			127 if buffer.len() >= 10 => (u64::from_be_bytes(buffer[2..10].try_into().unwrap()), 10),
+			126 | 127 => return Ok(None),
+			length => (length as u64, 2),
+		};
+		if length > MAX_MESSAGE as u64 {
+			return Err("The frame is too large".to_string());
+		}
+
+		let mut mask = [0u8; 4];
+		if masked {
+			let Some(bytes) = buffer.get(offset..offset + 4) else { return Ok(None) };
+			mask.copy_from_slice(bytes);
+			offset += 4;
+		}
+		let Some(payload) = buffer.get(offset..offset + length as usize) else { return Ok(None) };
+		let payload: Vec<u8> = match masked {
+			true => payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]).collect(),
+			false => payload.to_vec(),
+		};
+
+		self.buffer.drain(..offset + length as usize);
+		return Ok(Some((fin, opcode, payload)));
+	}
+
+}
+
+// ---
+
+// A blocking client connection to a WebSocket server.
+pub struct WebSocket {
+	stream  : TcpStream,
+	decoder : Decoder,
+}
+
+impl WebSocket {
+
+	// Connects to a server, e.g. `ws://127.0.0.1:8080/ws`.
+	pub fn connect(url: &str) -> Result<Self, String> {
+
+		let address = url.strip_prefix("ws://").ok_or(format!("Not a WebSocket URL: {url}"))?;
+		let (host, path) = match address.find('/') {
+			Some(index) => address.split_at(index),
+			None => (address, "/"),
+		};
+
+		let mut stream = TcpStream::connect(host).map_err(|e| format!("Unable to connect to {host}: {e}"))?;
+		let mut reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+
+		// Request the upgrade, and check the server answered the key.
+		let key = base64(&rand::random::<[u8; 16]>());
+		write!(stream,
+			"GET {path} HTTP/1.1\r\nHost: {host}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {key}\r\nSec-WebSocket-Version: 13\r\n\r\n"
+		).map_err(|e| format!("Unable to send the handshake: {e}"))?;
+
+		let mut status = String::new();
+		reader.read_line(&mut status).map_err(|e| format!("Unable to read the handshake: {e}"))?;
+		if status.split_whitespace().nth(1) != Some("101") {
+			return Err(format!("The server refused the WebSocket: {}", status.trim()));
+		}
+		let mut accepted = false;
+		loop {
+			let mut line = String::new();
+			reader.read_line(&mut line).map_err(|e| format!("Unable to read the handshake: {e}"))?;
+			let line = line.trim();
+			if line.is_empty() {
+				break;
+			}
+			if let Some((name, value)) = line.split_once(':') {
+				if name.trim().eq_ignore_ascii_case("sec-websocket-accept") && value.trim() == accept_key(&key) {
+					accepted = true;
+				}
+			}
+		}
+		if ! accepted {
+			return Err("The server sent an invalid handshake".to_string());
+		}
+
+		// Keep any frames which arrived along with the handshake.
+		let mut decoder = Decoder::default();
+		decoder.push(reader.buffer());
+		return Ok(Self{stream, decoder});
+	}
+
+
+	pub fn send_text(&mut self, text: &str) -> std::io::Result<()> {
+		self.stream.write_all(&encode_frame(OPCODE_TEXT, text.as_bytes(), true))
+	}
+
+
+	// Receives a text message, returning None once the connection has been closed.
+	pub fn receive_text(&mut self) -> std::io::Result<Option<String>> {
+		loop {
+			let incoming = self.decoder.decode()
+				.map_err(|message| std::io::Error::new(std::io::ErrorKind::InvalidData, message))?;
+			match incoming {
+				Some(Incoming::Text(text)) => return Ok(Some(text)),
+				Some(Incoming::Ping(payload)) => self.stream.write_all(&encode_frame(OPCODE_PONG, &payload, true))?,
+				Some(Incoming::Close(payload)) => {
+					let _ = self.stream.write_all(&encode_frame(OPCODE_CLOSE, &payload, true));
+					return Ok(None);
+				},
+				None => {
+					let mut bytes = [0u8; 4096];
+					match self.stream.read(&mut bytes)? {
+						0 => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+						length => self.decoder.push(&bytes[..length]),
+					}
+				},
+			}
+		}
+	}
+
+}
+
+// ---
+
+fn accept_key(key: &str) -> String {
+	base64(&sha1(format!("{key}{GUID}").as_bytes()))
+}
+
+
+fn base64(data: &[u8]) -> String {
+
+	const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+	let mut encoded = String::new();
+	for chunk in data.chunks(3) {
+		let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+		let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+		for i in 0..4 {
+			if i <= chunk.len() {
+				encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+			}
+			else {
+				encoded.push('=');
+			}
+		}
+	}
+	return encoded;
+}
+
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+
+	let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+	// Pad the message with a single bit, zeroes and its length in bits, up to a multiple of 64 bytes.
+	let mut message = data.to_vec();
+	message.push(0x80);
+	while message.len() % 64 != 56 {
+		message.push(0);
+	}
+	message.extend((data.len() as u64 * 8).to_be_bytes());
+
+	for block in message.chunks(64) {
+
+		let mut w = [0u32; 80];
+		for i in 0..16 {
+			w[i] = u32::from_be_bytes([block[4 * i], block[4 * i + 1], block[4 * i + 2], block[4 * i + 3]]);
+		}
+		for i in 16..80 {
+			w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+		}
+
+		let [mut a, mut b, mut c, mut d, mut e] = h;
+		for (i, word) in w.iter().enumerate() {
+			let (f, k) = match i {
+				0..=19  => ((b & c) | (!b & d), 0x5A827999),
+				20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+				40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+				_       => (b ^ c ^ d, 0xCA62C1D6),
+			};
+			let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+			e = d;
+			d = c;
+			c = b.rotate_left(30);
+			b = a;
+			a = temp;
+		}
+
+		for (value, added) in h.iter_mut().zip([a, b, c, d, e]) {
+			*value = value.wrapping_add(added);
+		}
+	}
+
+	let mut digest = [0u8; 20];
+	for (i, value) in h.iter().enumerate() {
+		digest[4 * i..4 * i + 4].copy_from_slice(&value.to_be_bytes());
+	}
+	return digest;
+}
+
+// ---
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+	use std::net::TcpListener;
+
+	// A frame as a browser would send it, with a fixed mask and the given length encoding.
+	fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+		let mut frame = encode_frame(opcode, payload, false);
+		if ! fin {
+			frame[0] &= 0x7F;
+		}
+		let mask = [0x37, 0xFA, 0x21, 0x3D];
+		let header_length = frame.len() - payload.len();
+		frame[1] |= 0x80;
+		let mut masked = frame[..header_length].to_vec();
+		masked.extend(mask);
+		masked.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+		return masked;
+	}
+
+	fn decode_all(bytes: &[u8]) -> Result<Option<Incoming>, String> {
+		let mut decoder = Decoder::default();
+		decoder.push(bytes);
+		return decoder.decode();
+	}
+
+	#[test]
+	fn sha1_known_answers() {
+		let hex = |digest: [u8; 20]| digest.iter().map(|byte| format!("{byte:02x}")).collect::<String>();
+		assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+		assert_eq!(hex(sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+		assert_eq!(hex(sha1(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")), "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+		assert_eq!(hex(sha1(&[b'a'; 1000])), "291e9a6c66994949b57ba5e650361e98fc36b1ba");
+	}
+
+	#[test]
+	fn base64_known_answers() {
+		// The test vectors of RFC 4648.
+		assert_eq!(base64(b""), "");
+		assert_eq!(base64(b"f"), "Zg==");
+		assert_eq!(base64(b"fo"), "Zm8=");
+		assert_eq!(base64(b"foo"), "Zm9v");
+		assert_eq!(base64(b"foob"), "Zm9vYg==");
+		assert_eq!(base64(b"fooba"), "Zm9vYmE=");
+		assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+	}
+
+	#[test]
+	fn accept_key_matches_rfc() {
+		// The example handshake of RFC 6455, section 1.3.
+		assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+	}
+
+	#[test]
+	fn accept_answers_upgrade() {
+		let request = b"GET /ws HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n";
+		let (request, _) = http::Request::parse(request).unwrap().unwrap();
+		let response = String::from_utf8(accept(&request).unwrap().to_bytes()).unwrap();
+		assert!(response.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+		assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+		let (request, _) = http::Request::parse(b"GET /ws HTTP/1.1\r\n\r\n").unwrap().unwrap();
+		assert!(accept(&request).is_err());
+	}
+
+	#[test]
+	fn decodes_masked_frame() {
+		// The single-frame masked text message example of RFC 6455, section 5.7.
+		let frame = [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58];
+		assert_eq!(decode_all(&frame), Ok(Some(Incoming::Text("Hello".to_string()))));
+		assert_eq!(decode_all(&client_frame(true, OPCODE_TEXT, b"Hello")), Ok(Some(Incoming::Text("Hello".to_string()))));
+	}
+
+	#[test]
+	fn decodes_unmasked_frames() {
+		let mut decoder = Decoder::default();
+		decoder.push(&encode_frame(OPCODE_TEXT, b"first", false));
+		decoder.push(&encode_frame(OPCODE_PING, b"ping", false));
+		decoder.push(&encode_frame(OPCODE_CLOSE, &[0x03, 0xE8], false));
+		assert_eq!(decoder.decode(), Ok(Some(Incoming::Text("first".to_string()))));
+		assert_eq!(decoder.decode(), Ok(Some(Incoming::Ping(b"ping".to_vec()))));
+		assert_eq!(decoder.decode(), Ok(Some(Incoming::Close(vec![0x03, 0xE8]))));
+		assert_eq!(decoder.decode(), Ok(None));
+	}
+
+	#[test]
+	fn reassembles_fragmented_message() {
+		let mut decoder = Decoder::default();
+		decoder.push(&client_frame(false, OPCODE_TEXT, b"Hel"));
+		assert_eq!(decoder.decode(), Ok(None));
+
+		// Control frames may arrive between the fragments.
+		decoder.push(&client_frame(true, OPCODE_PING, b""));
+		assert_eq!(decoder.decode(), Ok(Some(Incoming::Ping(Vec::new()))));
+		decoder.push(&client_frame(false, OPCODE_CONTINUATION, b"l"));
+		decoder.push(&client_frame(true, OPCODE_CONTINUATION, b"o"));
+		assert_eq!(decoder.decode(), Ok(Some(Incoming::Text("Hello".to_string()))));
+	}
+
+	#[test]
+	fn decodes_extended_lengths() {
+		for length in [125, 126, 127, 0xFFFF, 0x10000, 300_000] {
+			let text = "x".repeat(length);
+			let frame = encode_frame(OPCODE_TEXT, text.as_bytes(), false);
+			let header_length = match length {
+				0..=125 => 2,
+				126..=0xFFFF => 4,
+				_ => 10,
+			};
+			assert_eq!(frame.len(), header_length + length, "header of a {length} byte payload");
+			assert_eq!(decode_all(&frame), Ok(Some(Incoming::Text(text.clone()))));
+			assert_eq!(decode_all(&client_frame(true, OPCODE_TEXT, text.as_bytes())), Ok(Some(Incoming::Text(text))));
+		}
+	}
+
+	#[test]
+	fn waits_for_partial_frames() {
+		let frame = client_frame(true, OPCODE_TEXT, "y".repeat(200).as_bytes());
+		let mut decoder = Decoder::default();
+
+		// Every prefix is incomplete, including those cutting the extended length and the mask.
+		for byte in &frame[..frame.len() - 1] {
+			decoder.push(&[*byte]);
+			assert_eq!(decoder.decode(), Ok(None));
+		}
+		decoder.push(&frame[frame.len() - 1..]);
+		assert_eq!(decoder.decode(), Ok(Some(Incoming::Text("y".repeat(200)))));
+	}
+
+	#[test]
+	fn rejects_invalid_frames() {
+		assert!(decode_all(&encode_frame(0x2, b"binary", false)).is_err());
+		assert!(decode_all(&encode_frame(OPCODE_TEXT, &[0xFF, 0xFE], false)).is_err());
+		assert!(decode_all(&encode_frame(OPCODE_TEXT, &vec![b'z'; MAX_MESSAGE + 1], false)).is_err());
+	}
+
+	#[test]
+	fn loopback_round_trip() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let address = listener.local_addr().unwrap();
+
+		// An echo server, answering the handshake and then sending every message back.
+		let server = std::thread::spawn(move || {
+			let (mut stream, _) = listener.accept().unwrap();
+			let mut input = Vec::new();
+			let mut bytes = [0u8; 4096];
+			let (request, length) = loop {
+				let length = stream.read(&mut bytes).unwrap();
+				input.extend_from_slice(&bytes[..length]);
+				if let Some(request) = http::Request::parse(&input).unwrap() { break request; }
+			};
+			stream.write_all(&accept(&request).unwrap().to_bytes()).unwrap();
+
+			let mut decoder = Decoder::default();
+			decoder.push(&input[length..]);
+			loop {
+				match decoder.decode().unwrap() {
+					Some(Incoming::Text(text)) => stream.write_all(&encode_frame(OPCODE_TEXT, text.as_bytes(), false)).unwrap(),
+					Some(Incoming::Close(payload)) => {
+						stream.write_all(&encode_frame(OPCODE_CLOSE, &payload, false)).unwrap();
+						return;
+					},
+					Some(Incoming::Ping(payload)) => stream.write_all(&encode_frame(OPCODE_PONG, &payload, false)).unwrap(),
+					None => match stream.read(&mut bytes).unwrap() {
+						0 => return,
+						length => decoder.push(&bytes[..length]),
+					},
+				}
+			}
+		});
+
+		let mut client = WebSocket::connect(&format!("ws://{address}/ws")).unwrap();
+		for text in ["{\"type\":\"Hello\"}", "", &"long".repeat(1000)] {
+			client.send_text(text).unwrap();
+			assert_eq!(client.receive_text().unwrap().as_deref(), Some(text));
+		}
+		client.stream.write_all(&encode_frame(OPCODE_CLOSE, &[], true)).unwrap();
+		assert_eq!(client.receive_text().unwrap(), None);
+		server.join().unwrap();
+	}
+
+}
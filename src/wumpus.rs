@@ -4,27 +4,24 @@ use std::collections::HashSet;
 
 use rand::{
 	distributions::{Distribution, Standard},
+	rngs::StdRng,
 	Rng,
+	SeedableRng,
 };
 
 use serde::{Serialize, Deserialize};
 
 // ---
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub enum Direction {
+	#[default]
 	East,
 	South,
 	West,
 	North,
 }
 
-impl Default for Direction {
-	fn default() -> Self {
-		Self::East
-	}
-}
-
 impl std::fmt::Display for Direction {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		write!(f, "{}", match self {
@@ -92,7 +89,7 @@ impl Direction {
 
 // ---
 
-#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct Coordinate {
 	pub x: i32,
 	pub y: i32,
@@ -192,7 +189,7 @@ impl Class {
 
 // ---
 
-#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub struct ClassField<T> {
 	pub empty:    T,
 	pub treasure: T,
@@ -211,7 +208,7 @@ impl<T: ToString> std::fmt::Display for ClassField<T> {
 
 // ---
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Action {
 	Walk,
 	Left,
@@ -260,7 +257,7 @@ impl Distribution<Action> for Standard {
 
 // ---
 
-#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Events {
 	pub treasure : bool, // The player dug up a treasure.
 	pub wumpus   : bool, // The player is on a wumpus.
@@ -271,6 +268,10 @@ pub struct Events {
 	pub bonked   : bool, // The player walked into a wall.
 	pub scream   : bool, // The player killed the wumpus.
 	pub gameover : bool, // The player found the treasure.
+	#[serde(default)]
+	pub shot     : bool, // The player was shot by another player.
+	#[serde(default)]
+	pub stolen   : u32,  // The treasures dug up by other players since the last action of the player.
 }
 
 impl std::fmt::Display for Events {
@@ -285,6 +286,8 @@ impl std::fmt::Display for Events {
 		if self.bonked   { s.push("bonked") }
 		if self.scream   { s.push("scream") }
 		if self.gameover { s.push("gameover") }
+		if self.shot     { s.push("shot") }
+		if self.stolen > 0 { s.push("stolen") }
 		write!(f, "{}", s.join(","))
 	}
 }
@@ -303,6 +306,8 @@ impl FromStr for Events {
 			bonked   : words.contains(&"bonked"),
 			scream   : words.contains(&"scream"),
 			gameover : words.contains(&"gameover"),
+			shot     : words.contains(&"shot"),
+			stolen   : words.contains(&"stolen") as u32,
 		})
 	}
 }
@@ -395,9 +400,9 @@ impl Map {
 	pub fn apply_classes(&mut self, locations: &[Coordinate], classes: &[Class]) {
 		debug_assert_eq!(locations.len(), classes.len());
 		for (location, class) in std::iter::zip(locations, classes) {
-			self.treasures.remove(&location);
-			self.wumpuses.remove(&location);
-			self.pits.remove(&location);
+			self.treasures.remove(location);
+			self.wumpuses.remove(location);
+			self.pits.remove(location);
 			match class {
 				Class::Treasure => { self.treasures.insert(*location); },
 				Class::Wumpus   => { self.wumpuses.insert(*location); },
@@ -415,6 +420,236 @@ impl Map {
 			.collect()
 	}
 
+
+	// Loads a map written in the text format.
+	pub fn load(path: &str) -> Result<Self, String> {
+		let text = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+		text.parse().map_err(|e| format!("Invalid map in {path}: {e}"))
+	}
+
+
+	// Loads a single map file, or every map file of a directory sorted by name, along with their names.
+	// Only the files of a directory ending in .txt or .map are maps, so that it may hold a readme or backups.
+	pub fn load_all(path: &str) -> Result<Vec<(String, Self)>, String> {
+		let name = |path: &std::path::Path| path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+		if ! std::path::Path::new(path).is_dir() {
+			return Ok(vec![(name(std::path::Path::new(path)), Self::load(path)?)]);
+		}
+
+		let mut paths: Vec<std::path::PathBuf> = std::fs::read_dir(path)
+			.map_err(|e| format!("Unable to read {path}: {e}"))?
+			.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+			.filter(|path| path.is_file() && path.extension().is_some_and(|extension| extension == "txt" || extension == "map"))
+			.collect();
+		paths.sort();
+		if paths.is_empty() {
+			return Err(format!("There are no maps (.txt or .map files) in {path}"));
+		}
+		paths
+			.iter()
+			.map(|path| Ok((name(path), Self::load(&path.to_string_lossy())?)))
+			.collect()
+	}
+
+}
+
+/* Maps can be written by hand as a grid of tiles, the top row being the northernmost one, e.g:
+
+	# A wumpus guards the treasure.
+	. . . T
+	. . W .
+	. P . .
+	S . . .
+
+Every tile is one of . (empty), T (treasure), W (wumpus), P (pit) or S (the spawn location, which is
+always the bottom left tile and must be empty). Spaces between the tiles, blank lines and lines starting
+with # are ignored. */
+
+impl FromStr for Map {
+	type Err = String;
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+
+		let error = |line: usize, column: usize, message: &str| format!("Line {line}, column {column}: {message}");
+
+		// Read the rows, remembering the line and column of every tile for the errors.
+		let mut rows: Vec<(usize, Vec<(usize, char)>)> = Vec::new();
+		for (line, text) in s.lines().enumerate() {
+			let trimmed = text.trim();
+			if trimmed.is_empty() || trimmed.starts_with('#') { continue; }
+			let tiles = text
+				.chars()
+				.enumerate()
+				.filter(|(_, tile)| ! tile.is_whitespace())
+				.map(|(column, tile)| (column + 1, tile))
+				.collect();
+			rows.push((line + 1, tiles));
+		}
+		let Some((_, first_row)) = rows.first() else {
+			return Err("The map has no tiles".to_string());
+		};
+
+		let width = first_row.len();
+		let height = rows.len();
+		let mut map = Map {
+			size : Coordinate{x: width as i32 - 1, y: height as i32 - 1},
+			.. Default::default()
+		};
+		for (row, (line, tiles)) in rows.iter().enumerate() {
+
+			// Point at the first extra tile, or right after the last one.
+			if tiles.len() != width {
+				let column = match tiles.get(width) {
+					Some(&(column, _)) => column,
+					None => tiles.last().map_or(1, |&(column, _)| column + 1),
+				};
+				return Err(error(*line, column, &format!("Expected {width} tiles like the first row, but found {}", tiles.len())));
+			}
+
+			for (x, &(column, tile)) in tiles.iter().enumerate() {
+				let location = Coordinate{x: x as i32, y: (height - 1 - row) as i32};
+				let spawn = location == Game::SPAWN_LOCATION;
+				match tile.to_ascii_uppercase() {
+					'.' => (),
+					'S' if spawn => (),
+					'S' => return Err(error(*line, column, "The spawn location is always the bottom left tile")),
+					'T' | 'W' | 'P' if spawn => return Err(error(*line, column, "The spawn location must be empty")),
+					'T' => map.add_treasure(location),
+					'W' => map.add_wumpus(location),
+					'P' => map.add_pit(location),
+					_ => return Err(error(*line, column, &format!("Unknown tile '{tile}', expected one of . T W P S"))),
+				}
+			}
+		}
+
+		if map.treasures.is_empty() {
+			return Err("The map needs at least one treasure".to_string());
+		}
+		return Ok(map);
+	}
+}
+
+impl std::fmt::Display for Map {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		for y in (0..=self.size.y).rev() {
+			let row: Vec<&str> = (0..=self.size.x)
+				.map(|x| Coordinate{x, y})
+				.map(|location| match location {
+					_ if self.treasures.contains(&location) => "T",
+					_ if self.wumpuses.contains(&location)  => "W",
+					_ if self.pits.contains(&location)      => "P",
+					Game::SPAWN_LOCATION                    => "S",
+					_                                       => ".",
+				})
+				.collect();
+			writeln!(f, "{}", row.join(" "))?;
+		}
+		Ok(())
+	}
+}
+
+// ---
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rules {
+	pub size_x          : i32,
+	pub size_y          : i32,
+	pub spawn_arrows    : i32,
+	pub count_treasures : i32,
+	pub count_wumpuses  : i32,
+	pub count_pits      : i32,
+	pub score_action    : i32, // When a movement is performed.
+	pub score_shot      : i32, // When shooting an arrow.
+	pub score_dug       : i32, // When digging for a treasure.
+	pub score_treasure  : i32, // When finding a treasure.
+	pub score_wumpus    : i32, // When players walks into a wumpus.
+	pub score_pit       : i32, // When falling into a pit.
+}
+
+impl Default for Rules {
+	fn default() -> Self {
+		Self {
+			size_x          : Game::SIZE_X,
+			size_y          : Game::SIZE_Y,
+			spawn_arrows    : Game::SPAWN_ARROWS,
+			count_treasures : Game::COUNT_TREASURES,
+			count_wumpuses  : Game::COUNT_WUMPUSES,
+			count_pits      : Game::COUNT_PITS,
+			score_action    : Game::SCORE_ACTION,
+			score_shot      : Game::SCORE_SHOT,
+			score_dug       : Game::SCORE_DUG,
+			score_treasure  : Game::SCORE_TREASURE,
+			score_wumpus    : Game::SCORE_WUMPUS,
+			score_pit       : Game::SCORE_PIT,
+		}
+	}
+}
+
+impl Rules {
+
+	// The rules for playing on a map, with its size and number of treasures, wumpuses and pits.
+	pub fn with_map(&self, map: &Map) -> Self {
+		Self {
+			size_x          : map.size.x + 1,
+			size_y          : map.size.y + 1,
+			count_treasures : map.treasures.len() as i32,
+			count_wumpuses  : map.wumpuses.len() as i32,
+			count_pits      : map.pits.len() as i32,
+			.. self.clone()
+		}
+	}
+
+	// Checks the rules derived from every map, as a map may break rules which are valid on their own.
+	pub fn validate_maps(&self, maps: &[(String, Map)]) -> Result<(), String> {
+		for (name, map) in maps {
+			self.with_map(map).validate().map_err(|e| format!("Invalid rules for the map {name}: {e}"))?;
+		}
+		Ok(())
+	}
+
+	pub fn load(path: &str) -> Result<Self, String> {
+		let file = std::fs::File::open(path).map_err(|e| format!("Unable to open {path}: {e}"))?;
+		let rules: Self = serde_json::from_reader(file).map_err(|e| format!("Invalid rules in {path}: {e}"))?;
+		rules.validate().map_err(|e| format!("Invalid rules in {path}: {e}"))?;
+		Ok(rules)
+	}
+
+	/* Rules may come from clients, so they are bounded as well: larger maps take too long to generate and
+	to reason about, blocking the server, and larger scores could overflow over a long game. */
+	pub const MAX_SIZE  : i32 = 64;
+	pub const MAX_SCORE : i32 = 100_000;
+
+	pub fn validate(&self) -> Result<(), String> {
+
+		if self.size_x < 1 || self.size_y < 1 {
+			return Err("The map must be at least 1x1".to_string());
+		}
+		if self.size_x > Self::MAX_SIZE || self.size_y > Self::MAX_SIZE {
+			return Err(format!("The map can be at most {0}x{0}", Self::MAX_SIZE));
+		}
+
+		if self.count_treasures < 1 || self.count_wumpuses < 0 || self.count_pits < 0 || self.spawn_arrows < 0 {
+			return Err("There must be at least 1 treasure, and no negative counts".to_string());
+		}
+
+		// Every special location needs its own tile, besides the spawn location.
+		let count = self.count_treasures as i64 + self.count_wumpuses as i64 + self.count_pits as i64;
+		if count >= self.size_x as i64 * self.size_y as i64 {
+			return Err("There are more treasures, wumpuses and pits than the map can fit".to_string());
+		}
+
+		// Pathfinding relies on actions and hazards never being rewarding.
+		if self.score_action > 0 || self.score_wumpus > 0 || self.score_pit > 0 {
+			return Err("Actions, wumpuses and pits cannot increase the score".to_string());
+		}
+		let scores = [self.score_action, self.score_shot, self.score_dug, self.score_treasure, self.score_wumpus, self.score_pit];
+		if scores.iter().any(|score| score.unsigned_abs() > Self::MAX_SCORE as u32) || self.spawn_arrows > Self::MAX_SCORE {
+			return Err(format!("Scores and arrows can be at most {} in magnitude", Self::MAX_SCORE));
+		}
+
+		Ok(())
+	}
+
 }
 
 // ---
@@ -428,6 +663,8 @@ pub struct Game {
 	pub game_over  : bool,
 	pub score      : i32,
 	pub arrows     : i32,
+	#[serde(default)]
+	pub rules      : Rules,
 }
 
 impl Default for Game {
@@ -440,6 +677,7 @@ impl Default for Game {
 			game_over : Default::default(),
 			score     : Default::default(),
 			arrows    : 1,
+			rules     : Default::default(),
 		}
 	}
 }
@@ -457,7 +695,7 @@ impl Game {
 	pub const COUNT_WUMPUSES  : i32 = 1;
 	pub const COUNT_PITS      : i32 = 3;
 
-	pub const SCORE_ACTION   : i32 = -1;   // When a movement is poerformed.
+	pub const SCORE_ACTION   : i32 = -1;   // When a movement is performed.
 	pub const SCORE_SHOT     : i32 = -10;  // When shooting an arrow.
 	pub const SCORE_DUG      : i32 = -50;  // When digging for a treasure.
 	pub const SCORE_TREASURE : i32 =  250; // When finding a treasure.
@@ -465,34 +703,60 @@ impl Game {
 	pub const SCORE_PIT      : i32 = -100; // When falling into a pit.
 
 
-	pub fn new_random() -> Self {
+	pub fn new_random(rules: &Rules) -> Self {
+		Self::new_with_rng(rules, &mut rand::thread_rng())
+	}
+
+
+	pub fn new_seeded(rules: &Rules, seed: u64) -> Self {
+		Self::new_with_rng(rules, &mut StdRng::seed_from_u64(seed))
+	}
+
+
+	pub fn new_with_rng<R: Rng + ?Sized>(rules: &Rules, rng: &mut R) -> Self {
 
 		// Create a new map
-		let mut map: Map = Default::default();
-		map.discovered.insert(Self::SPAWN_LOCATION);
+		let mut map: Map = Map{
+			size: Coordinate{x: rules.size_x - 1, y: rules.size_y - 1},
+			.. Default::default()
+		};
 
 		// Generate special locations
-		let special_location_count = Self::COUNT_TREASURES + Self::COUNT_WUMPUSES + Self::COUNT_PITS;
+		let special_location_count = rules.count_treasures + rules.count_wumpuses + rules.count_pits;
 		let mut special_locations: Vec<Coordinate> = vec![Self::SPAWN_LOCATION];
+		let mut taken: HashSet<Coordinate> = HashSet::from([Self::SPAWN_LOCATION]);
 
 		while special_locations.len() <= special_location_count as usize {
-			let random_location: Coordinate = rand::random();
-			if ! special_locations.contains(&random_location) {
+			let random_location = Coordinate {
+				x: rng.gen_range(0..rules.size_x),
+				y: rng.gen_range(0..rules.size_y),
+			};
+			if taken.insert(random_location) {
 				special_locations.push(random_location);
 			}
 		}
 
 		// Insert special locations into map
 		let mut iter = special_locations.iter().skip(1);
-		for location in iter.by_ref().take(Self::COUNT_TREASURES as usize) { map.add_treasure(*location); }
-		for location in iter.by_ref().take(Self::COUNT_WUMPUSES as usize)  { map.add_wumpus(*location); }
-		for location in iter.by_ref().take(Self::COUNT_PITS as usize)      { map.add_pit(*location); }
+		for location in iter.by_ref().take(rules.count_treasures as usize) { map.add_treasure(*location); }
+		for location in iter.by_ref().take(rules.count_wumpuses as usize)  { map.add_wumpus(*location); }
+		for location in iter.by_ref().take(rules.count_pits as usize)      { map.add_pit(*location); }
+
+		return Self::from_map(rules, map);
+	}
+
+
+	// Starts a game on the given map, with the player at the spawn location.
+	pub fn from_map(rules: &Rules, mut map: Map) -> Self {
+
+		map.discovered.insert(Self::SPAWN_LOCATION);
 
 		// Build the game struct
 		let mut game = Self {
 			map       : map,
 			direction : Self::SPAWN_DIRECTION,
-			arrows    : Self::SPAWN_ARROWS,
+			arrows    : rules.spawn_arrows,
+			rules     : rules.clone(),
 			.. Default::default()
 		};
 
@@ -514,16 +778,16 @@ impl Game {
 
 		self.map.discovered.insert(*new_location);
 
-		if self.map.wumpuses.contains(&new_location) {
+		if self.map.wumpuses.contains(new_location) {
 			self.game_over = true;
 			self.events.gameover = true;
 			self.events.wumpus = true;
-			self.score += Self::SCORE_WUMPUS;
+			self.score += self.rules.score_wumpus;
 		}
 
-		if self.map.pits.contains(&new_location) {
+		if self.map.pits.contains(new_location) {
 			self.events.pit = true;
-			self.score += Self::SCORE_PIT;
+			self.score += self.rules.score_pit;
 		}
 
 		self.location = *new_location;
@@ -538,7 +802,7 @@ impl Game {
 		}
 
 		self.events = Default::default();
-		self.score += Self::SCORE_ACTION;
+		self.score += self.rules.score_action;
 		
 		match action {
 
@@ -561,10 +825,10 @@ impl Game {
 			},
 
 			Action::Dig => {
-				self.score += Self::SCORE_DUG;
+				self.score += self.rules.score_dug;
 				if self.map.treasures.contains(&self.location) {
 					self.map.remove_treasure(self.location);
-					self.score += Self::SCORE_TREASURE;
+					self.score += self.rules.score_treasure;
 					self.events.treasure = true;
 				}
 				if self.map.treasures.is_empty() {
@@ -576,7 +840,7 @@ impl Game {
 			Action::Shoot => {
 				if self.arrows > 0 {
 					self.arrows -= 1;
-					self.score += Self::SCORE_SHOT;
+					self.score += self.rules.score_shot;
 					let front_location = self.location.get_front(&self.direction);
 					if self.map.wumpuses.contains(&front_location) {
 						self.map.remove_wumpus(front_location);
@@ -590,5 +854,104 @@ impl Game {
 		self.update_senses();
 	}
 
+
+	// Describes the events caused by the last action, along with how the game ended.
+	pub fn describe_events(&self) -> Vec<&'static str> {
+		let mut messages = Vec::new();
+		if self.events.bonked   { messages.push("You hit your head against the wall. Ouch!"); }
+		if self.events.scream   { messages.push("A terrible scream echoes throughout the cave..."); }
+		if self.events.treasure { messages.push("You found a treasure! Congratulations!"); }
+		if self.events.stolen > 0 { messages.push("You hear another player digging up a treasure."); }
+		if self.events.pit      { messages.push("Oh no, you fell into a pit :("); }
+		if self.game_over {
+			if self.events.wumpus {
+				messages.push("You walked into a wumpus den. GG");
+			}
+			else if self.events.shot {
+				messages.push("You were shot by another player. GG");
+			}
+			else if self.map.treasures.is_empty() {
+				messages.push("All treasures have been found. GG");
+			}
+			else {
+				messages.push("The game is over.");
+			}
+		}
+		return messages;
+	}
+
 }
 
+
+// ---
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	fn coordinates(locations: &[(i32, i32)]) -> HashSet<Coordinate> {
+		locations.iter().map(|&(x, y)| Coordinate{x, y}).collect()
+	}
+
+	#[test]
+	fn parses_maps() {
+		let map: Map = "# A small cave.\n\n. . W .\n. p T .\n\n. . . .\nS . P .\n".parse().unwrap();
+		assert_eq!(map.size, Coordinate{x: 3, y: 3});
+		assert_eq!(map.treasures, coordinates(&[(2, 2)]));
+		assert_eq!(map.wumpuses, coordinates(&[(2, 3)]));
+		assert_eq!(map.pits, coordinates(&[(1, 2), (2, 0)]));
+		assert!(map.glitters.contains(&Coordinate{x: 2, y: 1}));
+		assert!(map.stenches.contains(&Coordinate{x: 3, y: 3}));
+		assert!(map.breezes.contains(&Coordinate{x: 1, y: 0}));
+
+		// The spawn location may be left unmarked, and the tiles don't need spaces between them.
+		let map: Map = "..T\n...".parse().unwrap();
+		assert_eq!(map.size, Coordinate{x: 2, y: 1});
+		assert_eq!(map.treasures, coordinates(&[(2, 1)]));
+	}
+
+	#[test]
+	fn reports_errors_with_their_location() {
+		let error = |text: &str| text.parse::<Map>().unwrap_err();
+
+		assert_eq!(error(""), "The map has no tiles");
+		assert_eq!(error("# Only a comment\n\n"), "The map has no tiles");
+		assert_eq!(error(". T .\n. . . .\nS . ."), "Line 2, column 7: Expected 3 tiles like the first row, but found 4");
+		assert_eq!(error("# Comment\n. T .\n. .\nS . ."), "Line 3, column 4: Expected 3 tiles like the first row, but found 2");
+		assert_eq!(error(". T\nS X"), "Line 2, column 3: Unknown tile 'X', expected one of . T W P S");
+		assert_eq!(error(". S\n. T"), "Line 1, column 3: The spawn location is always the bottom left tile");
+		assert_eq!(error(". T\nW ."), "Line 2, column 1: The spawn location must be empty");
+		assert_eq!(error(". W\nS P"), "The map needs at least one treasure");
+	}
+
+	#[test]
+	fn rejects_oversized_rules() {
+		let rules = |rules: Rules| rules.validate().unwrap_err();
+		assert_eq!(rules(Rules{size_x: 100_000, size_y: 100_000, .. Default::default()}), "The map can be at most 64x64");
+		assert_eq!(rules(Rules{size_y: Rules::MAX_SIZE + 1, .. Default::default()}), "The map can be at most 64x64");
+		assert_eq!(rules(Rules{count_treasures: i32::MAX, count_pits: i32::MAX, .. Default::default()}), "There are more treasures, wumpuses and pits than the map can fit");
+		assert!(rules(Rules{score_treasure: i32::MAX, .. Default::default()}).starts_with("Scores and arrows"));
+		assert!(rules(Rules{score_pit: i32::MIN, .. Default::default()}).starts_with("Scores and arrows"));
+		assert!(Rules{size_x: Rules::MAX_SIZE, size_y: Rules::MAX_SIZE, .. Default::default()}.validate().is_ok());
+	}
+
+	#[test]
+	fn round_trips_through_display() {
+		let hand_drawn: Map = ". . W .\n. P T .\n. . . .\nS . P .".parse().unwrap();
+		let generated = (0..20).map(|seed| Game::new_seeded(&Default::default(), seed).map);
+		for map in std::iter::once(hand_drawn).chain(generated) {
+			let text = map.to_string();
+			let parsed: Map = text.parse().unwrap_or_else(|e| panic!("{e} in\n{text}"));
+			assert_eq!(parsed.size, map.size);
+			assert_eq!(parsed.treasures, map.treasures);
+			assert_eq!(parsed.wumpuses, map.wumpuses);
+			assert_eq!(parsed.pits, map.pits);
+			assert_eq!(parsed.glitters, map.glitters);
+			assert_eq!(parsed.stenches, map.stenches);
+			assert_eq!(parsed.breezes, map.breezes);
+			assert_eq!(parsed.to_string(), text);
+		}
+	}
+
+}
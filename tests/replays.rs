@@ -0,0 +1,24 @@
+use wumpusworld::replay::Replay;
+
+/* Every replay in `tests/replays` is a recorded game, which has to play out the same way under the
+current rules of the game. Add the replay of a game showing a bug here once it's fixed. */
+
+#[test]
+fn recorded_replays_play_out_the_same() {
+	let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/replays");
+	let mut count = 0;
+	for entry in std::fs::read_dir(directory).unwrap() {
+		let path = entry.unwrap().path();
+		if path.extension().is_none_or(|extension| extension != "json") {
+			continue;
+		}
+		let path = path.to_string_lossy();
+		let replay = Replay::load(&path).unwrap();
+		let games = replay.play().unwrap_or_else(|message| panic!("{path}: {message}"));
+		let last = games.last().unwrap();
+		assert!(last.game_over, "{path}: the game isn't over");
+		assert_eq!(Some(last.score), replay.steps.last().map(|step| step.score), "{path}");
+		count += 1;
+	}
+	assert!(count >= 2, "the seeded and the map fixtures are missing");
+}